@@ -0,0 +1,261 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{Task, TaskList};
+
+/*** Activity log ***/
+// One JSON event per line, only ever appended to, next to the board file as <file>.log
+
+/*
+* Something that happened to a list or task
+* time: DateTime<Local> - When it happened
+* list: Uuid - The list, or the list the task was in afterwards
+* task: Option<Uuid> - The task, None for events about the list itself
+* action: Action - What happened
+* title: String - Title of the list or task at the time
+* detail: String - Extra information, e.g. the old and new due date
+*/
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Event {
+    pub time: DateTime<Local>,
+    pub list: Uuid,
+    #[serde(default)]
+    pub task: Option<Uuid>,
+    pub action: Action,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub detail: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Edit,
+    Reschedule,
+    Complete,
+    Reopen,
+    Move,
+    Delete,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Action::Create => "created",
+            Action::Edit => "edited",
+            Action::Reschedule => "rescheduled",
+            Action::Complete => "completed",
+            Action::Reopen => "reopened",
+            Action::Move => "moved",
+            Action::Delete => "deleted",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+pub fn append_events(path: &Path, events: &[Event]) -> io::Result<()> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    let mut lines = String::new();
+    for event in events {
+        lines.push_str(&serde_json::to_string(event)?);
+        lines.push('\n');
+    }
+    let mut log = OpenOptions::new().create(true).append(true).open(path)?;
+    log.write_all(lines.as_bytes())?;
+    log.sync_data()
+}
+
+// Every event that still parses, oldest first
+pub fn read_events(path: &Path) -> Vec<Event> {
+    fs::read_to_string(path).unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/*** Turning changes into events ***/
+fn event(list: Uuid, task: Option<Uuid>, action: Action, title: &str, detail: String) -> Event {
+    Event { time: Local::now(), list, task, action, title: title.to_string(), detail }
+}
+
+pub fn list_events(before: Option<&TaskList>, after: Option<&TaskList>) -> Vec<Event> {
+    match (before, after) {
+        (None, Some(list)) => vec![event(list.id, None, Action::Create, &list.title, String::new())],
+        (Some(list), None) => vec![event(list.id, None, Action::Delete, &list.title, String::new())],
+        (Some(old), Some(list)) if old.title != list.title => {
+            vec![event(list.id, None, Action::Edit, &list.title, format!("renamed from \"{}\"", old.title))]
+        }
+        _ => vec![],
+    }
+}
+
+fn due_label(task: &Task) -> String {
+    match (task.date_string.is_empty(), task.due_at) {
+        (true, _) => "no due date".to_string(),
+        (false, Some(due_at)) => due_at.format("%Y/%m/%d %H:%M").to_string(),
+        (false, None) => task.due.format("%Y/%m/%d").to_string(),
+    }
+}
+
+// A task edit can be several events at once, e.g. completing a task and moving its due date
+pub fn task_events(before: Option<(&Task, Uuid)>, after: Option<(&Task, Uuid)>) -> Vec<Event> {
+    let ((old, old_list), (task, list)) = match (before, after) {
+        (None, Some((task, list))) => {
+            let detail = match task.date_string.is_empty() {
+                true => String::new(),
+                false => format!("due {}", due_label(task)),
+            };
+            return vec![event(list, Some(task.id), Action::Create, &task.title, detail)];
+        }
+        (Some((task, list)), None) => return vec![event(list, Some(task.id), Action::Delete, &task.title, String::new())],
+        (Some(old), Some(new)) => (old, new),
+        (None, None) => return vec![],
+    };
+    let title = task.title.as_str();
+    let mut events = vec![];
+    if old_list != list {
+        events.push(event(list, Some(task.id), Action::Move, title, String::new()));
+    }
+    if old.done != task.done {
+        let action = if task.done { Action::Complete } else { Action::Reopen };
+        events.push(event(list, Some(task.id), action, title, String::new()));
+    }
+    let (old_due, new_due) = (due_label(old), due_label(task));
+    if old_due != new_due {
+        events.push(event(list, Some(task.id), Action::Reschedule, title, format!("{} -> {}", old_due, new_due)));
+    }
+    let mut fields = vec![];
+    if old.title != task.title {
+        fields.push(format!("renamed from \"{}\"", old.title));
+    }
+    for (changed, name) in [
+        (old.notes != task.notes, "notes"),
+        (old.checklist != task.checklist, "checklist"),
+        (old.priority != task.priority, "priority"),
+        (old.tags != task.tags, "tags"),
+        (old.recurrence != task.recurrence, "repeat rule"),
+        (old.blocked_by != task.blocked_by, "blockers"),
+        (old.parent != task.parent, "parent task"),
+    ] {
+        if changed {
+            fields.push(name.to_string());
+        }
+    }
+    if !fields.is_empty() {
+        events.push(event(list, Some(task.id), Action::Edit, title, fields.join(", ")));
+    }
+    events
+}
+
+/*** History view ***/
+// Newest first: everything that happened to the task, or to the list and the tasks in it
+pub fn timeline(events: &[Event], list: Uuid, task: Option<Uuid>) -> Vec<String> {
+    events.iter()
+        .rev()
+        .filter(|event| match task {
+            Some(id) => event.task == Some(id),
+            None => event.list == list,
+        })
+        .map(timeline_line)
+        .collect()
+}
+
+fn timeline_line(event: &Event) -> String {
+    let detail = match event.detail.is_empty() {
+        true => String::new(),
+        false => format!(" ({})", event.detail),
+    };
+    format!("{}  {} \"{}\"{}", event.time.format("%Y/%m/%d %H:%M"), event.action, event.title, detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    // Each event as its action and detail, e.g. "rescheduled (2024/05/03 -> 2024/05/10)"
+    fn actions(events: &[Event]) -> Vec<String> {
+        events.iter().map(|event| match event.detail.is_empty() {
+            true => event.action.to_string(),
+            false => format!("{} ({})", event.action, event.detail),
+        }).collect()
+    }
+
+    fn due(task: &mut Task, date: &str) {
+        task.date_string = date.to_string();
+        task.due = NaiveDate::parse_from_str(date, "%Y/%m/%d").unwrap();
+    }
+
+    /*** Turning changes into events ***/
+    #[test]
+    fn completing_and_reopening_a_task() {
+        let list = Uuid::new_v4();
+        let old = Task::new("Report".to_string());
+        let mut task = old.clone();
+        task.done = true;
+        assert_eq!(actions(&task_events(Some((&old, list)), Some((&task, list)))), ["completed"]);
+        assert_eq!(actions(&task_events(Some((&task, list)), Some((&old, list)))), ["reopened"]);
+        assert!(task_events(Some((&old, list)), Some((&old, list))).is_empty());
+    }
+
+    #[test]
+    fn due_date_changes_are_reschedules() {
+        let list = Uuid::new_v4();
+        let mut old = Task::new("Report".to_string());
+        due(&mut old, "2024/05/03");
+        let mut task = old.clone();
+        due(&mut task, "2024/05/10");
+        assert_eq!(actions(&task_events(Some((&old, list)), Some((&task, list)))), ["rescheduled (2024/05/03 -> 2024/05/10)"]);
+
+        task.date_string.clear();
+        assert_eq!(actions(&task_events(Some((&old, list)), Some((&task, list)))), ["rescheduled (2024/05/03 -> no due date)"]);
+    }
+
+    #[test]
+    fn a_task_moved_and_edited_is_one_event_each() {
+        let (work, home) = (Uuid::new_v4(), Uuid::new_v4());
+        let old = Task::new("Report".to_string());
+        let mut task = old.clone();
+        task.title = "Final report".to_string();
+        task.notes = "Send to Ann".to_string();
+        let events = task_events(Some((&old, work)), Some((&task, home)));
+        assert_eq!(actions(&events), ["moved", "edited (renamed from \"Report\", notes)"]);
+        assert!(events.iter().all(|event| event.list == home && event.task == Some(task.id) && event.title == "Final report"));
+    }
+
+    /*** History view ***/
+    #[test]
+    fn timeline_shows_a_task_or_everything_in_a_list() {
+        let work_list = crate::transfer::new_list("Work");
+        let (work, home) = (work_list.id, Uuid::new_v4());
+        let (report, laundry) = (Task::new("Report".to_string()), Task::new("Laundry".to_string()));
+        let mut done = report.clone();
+        done.done = true;
+        let events: Vec<Event> = [
+            list_events(None, Some(&work_list)),
+            task_events(None, Some((&report, work))),
+            task_events(None, Some((&laundry, home))),
+            task_events(Some((&report, work)), Some((&done, work))),
+        ].concat();
+
+        let task = timeline(&events, work, Some(report.id));
+        assert_eq!(task.len(), 2);
+        assert!(task[0].ends_with("completed \"Report\""));
+        assert!(task[1].ends_with("created \"Report\""));
+
+        let list = timeline(&events, work, None);
+        assert_eq!(list.len(), 3);
+        assert!(list[2].ends_with("created \"Work\""));
+        assert!(list.iter().all(|line| !line.contains("Laundry")));
+        assert_eq!(timeline(&events, home, None).len(), 1);
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+
+use crate::storage::Backend;
+use crate::transfer::Format;
+
+pub const USAGE: &str = "\
+Usage: taskboardcli [--db <path>] [--board <name>] [--list <title>] [command]
+
+Commands:
+  (none)              Open the board
+  restore             List backups of the board's file
+  restore <number>    Roll the board's file back to a backup
+  migrate-storage <json|sqlite>
+                      Move the board to another storage backend, keeping the
+                      old file next to the new one
+  export <format> [file]
+                      Write the board, or the list given with --list, to the
+                      file or to stdout. Formats: markdown, todotxt, ics
+                      (due tasks as to-dos), ics-events (as all-day events),
+                      csv, taskwarrior (JSON for `task import`)
+  import <format> <file>
+                      Add the file's tasks to the board, into lists of the
+                      same title or the one given with --list. Rows of a
+                      CSV file that cannot be read are shown first. Reads
+                      taskwarrior from the output of `task export`
+  todotxt-sync <file|off>
+                      Keep the list given with --list and a todo.txt file in
+                      sync while the board is open, or stop doing so
+
+Options:
+  --db <path>         DB file to use, overrides $TASKBOARD_DB and the default
+                      $XDG_DATA_HOME/taskboardcli/board.json
+  --board <name>      Open the named board instead of the main one, creating
+                      it if needed
+  --list <title>      List to export, import into or sync
+  --columns <field=header,...>
+                      CSV columns to read id, list, title, due, done,
+                      priority, tags and notes from, by default the column
+                      named like the field
+  --date-format <format>
+                      How CSV due dates are written, e.g. %d/%m/%Y, by
+                      default YYYY-MM-DD with an optional HH:MM
+  -h, --help          Show this message";
+
+pub enum Command {
+    Run,
+    Restore(Option<String>),
+    MigrateStorage(Backend),
+    Export(Format, Option<PathBuf>),
+    Import(Format, PathBuf),
+    TodoTxtSync(Option<PathBuf>),
+    Help,
+}
+
+/*
+* Parsed command line
+* db: Option<PathBuf> - DB file given with --db
+* board: Option<String> - Board given with --board
+* list: Option<String> - List title given with --list
+* columns: Vec<(String, String)> - CSV field to column mapping given with --columns
+* date_format: Option<String> - CSV date format given with --date-format
+* command: Command - What to do, opening the board by default
+*/
+pub struct Args {
+    pub db: Option<PathBuf>,
+    pub board: Option<String>,
+    pub list: Option<String>,
+    pub columns: Vec<(String, String)>,
+    pub date_format: Option<String>,
+    pub command: Command,
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut db = None;
+    let mut board = None;
+    let mut list = None;
+    let mut columns = vec![];
+    let mut date_format = None;
+    let mut positional = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--db" => match args.next() {
+                Some(path) => db = Some(PathBuf::from(path)),
+                None => return Err("--db needs a path".to_string()),
+            },
+            _ if arg.starts_with("--db=") => db = Some(PathBuf::from(&arg["--db=".len()..])),
+            "--board" => match args.next() {
+                Some(name) => board = Some(name),
+                None => return Err("--board needs a name".to_string()),
+            },
+            _ if arg.starts_with("--board=") => board = Some(arg["--board=".len()..].to_string()),
+            "--list" => match args.next() {
+                Some(title) => list = Some(title),
+                None => return Err("--list needs a list title".to_string()),
+            },
+            _ if arg.starts_with("--list=") => list = Some(arg["--list=".len()..].to_string()),
+            "--columns" => match args.next() {
+                Some(mapping) => columns = parse_columns(&mapping)?,
+                None => return Err("--columns needs a mapping such as title=Action,due=Deadline".to_string()),
+            },
+            _ if arg.starts_with("--columns=") => columns = parse_columns(&arg["--columns=".len()..])?,
+            "--date-format" => match args.next() {
+                Some(format) => date_format = Some(format),
+                None => return Err("--date-format needs a format such as %d/%m/%Y".to_string()),
+            },
+            _ if arg.starts_with("--date-format=") => date_format = Some(arg["--date-format=".len()..].to_string()),
+            "-h" | "--help" => return Ok(Args { db, board, list, columns, date_format, command: Command::Help }),
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let command = match positional.first().map(String::as_str) {
+        None => Command::Run,
+        Some("restore") if positional.len() <= 2 => Command::Restore(positional.get(1).cloned()),
+        Some("migrate-storage") if positional.len() == 2 => Command::MigrateStorage(positional[1].parse()?),
+        Some("export") if (2..=3).contains(&positional.len()) => Command::Export(positional[1].parse()?, positional.get(2).map(PathBuf::from)),
+        Some("import") if positional.len() == 3 => Command::Import(positional[1].parse()?, PathBuf::from(&positional[2])),
+        Some("todotxt-sync") if positional.len() == 2 && list.is_none() => return Err("todotxt-sync needs --list".to_string()),
+        Some("todotxt-sync") if positional.len() == 2 => Command::TodoTxtSync(Some(PathBuf::from(&positional[1])).filter(|file| file.as_os_str() != "off")),
+        Some(_) => return Err(format!("unknown command: {}", positional.join(" "))),
+    };
+    Ok(Args { db, board, list, columns, date_format, command })
+}
+
+// "title=Action,due=Deadline" into (field, header) pairs
+fn parse_columns(mapping: &str) -> Result<Vec<(String, String)>, String> {
+    mapping.split(',')
+        .map(|pair| match pair.split_once('=') {
+            Some((field, header)) if !field.trim().is_empty() && !header.trim().is_empty() => {
+                Ok((field.trim().to_lowercase(), header.trim().to_string()))
+            }
+            _ => Err(format!("cannot read the column mapping \"{}\", expected field=header", pair)),
+        })
+        .collect()
+}
@@ -0,0 +1,773 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, Local, NaiveDateTime};
+use home::home_dir;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::storage::{Backend, SQLITE_EXTENSIONS};
+use crate::sqlite;
+use crate::{Error, TagFilter, Task, TaskBoard, TaskList};
+
+/*** On-disk format ***/
+// Bump this and append a step to MIGRATIONS whenever the stored layout changes
+pub const DB_VERSION: u64 = 1;
+
+// How many timestamped copies of the DB file are kept next to it
+const BACKUP_COUNT: usize = 5;
+// A new backup is only taken once the newest one is at least this old
+const BACKUP_INTERVAL_MINUTES: i64 = 15;
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+// MIGRATIONS[n] upgrades a version n file to version n + 1
+const MIGRATIONS: [fn(Value) -> Value; DB_VERSION as usize] = [
+    migrate_v0_to_v1,
+];
+
+/*
+* Everything stored in the DB file
+* version: u64 - Format version the file was written with
+* board: BoardState - Board wide state that is not part of any list
+* lists: Vec<TaskList> - All lists on the board
+*/
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct DbFile {
+    pub version: u64,
+    #[serde(default)]
+    pub board: BoardState,
+    #[serde(default)]
+    pub lists: Vec<TaskList>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BoardState {
+    pub active_list: usize,
+    #[serde(default)]
+    pub tag_filter: TagFilter,
+}
+
+impl Default for BoardState {
+    fn default() -> Self {
+        BoardState {
+            active_list: 1,
+            tag_filter: TagFilter::default(),
+        }
+    }
+}
+
+impl From<&TaskBoard> for DbFile {
+    fn from(taskboard: &TaskBoard) -> Self {
+        DbFile {
+            version: DB_VERSION,
+            board: BoardState {
+                active_list: taskboard.active_list,
+                tag_filter: taskboard.tag_filter.clone(),
+            },
+            lists: taskboard.lists.clone(),
+        }
+    }
+}
+
+/*** Locating the DB file ***/
+// Environment variable that overrides the default DB location
+const DB_PATH_VAR: &str = "TASKBOARD_DB";
+
+fn home() -> PathBuf {
+    match home_dir() {
+        Some(path) if !path.as_os_str().is_empty() => path,
+        _ => std::process::exit(1),
+    }
+}
+
+// $XDG_DATA_HOME/taskboardcli/board.json, falling back to ~/.local/share. A board.sqlite left
+// there by `migrate-storage` takes precedence
+fn default_db_path() -> PathBuf {
+    let data_home = match std::env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+        Some(path) if path.is_absolute() => path,
+        _ => home().join(".local").join("share"),
+    };
+    let sqlite_path = data_home.join("taskboardcli").join("board.sqlite");
+    match sqlite_path.exists() {
+        true => sqlite_path,
+        false => data_home.join("taskboardcli").join("board.json"),
+    }
+}
+
+// --db wins over $TASKBOARD_DB, which wins over the default location. Returns the path and a
+// note for the status area when an old ~/.data.json was moved to the default location. Relative
+// paths are resolved against the working directory once, so boards and sidecars all agree on it
+pub fn resolve_db_path(flag: Option<PathBuf>) -> Result<(PathBuf, Option<String>), Error> {
+    if let Some(path) = flag {
+        return Ok((std::path::absolute(path)?, None));
+    }
+    if let Some(path) = std::env::var_os(DB_PATH_VAR).filter(|path| !path.is_empty()) {
+        return Ok((std::path::absolute(path)?, None));
+    }
+
+    let db_path = default_db_path();
+    let legacy_path = home().join(".data.json");
+    if db_path.exists() || !legacy_path.exists() {
+        return Ok((db_path, None));
+    }
+    fs::create_dir_all(db_path.parent().unwrap())?;
+    if fs::rename(&legacy_path, &db_path).is_err() {
+        // rename fails across filesystems
+        fs::copy(&legacy_path, &db_path)?;
+        fs::remove_file(&legacy_path)?;
+    }
+    let note = format!("Moved {} to {}", legacy_path.display(), db_path.display());
+    Ok((db_path, Some(note)))
+}
+
+/*** Named boards ***/
+// The board stored in the DB file itself, every other board lives in boards/<name>.<ext> next to it
+pub const MAIN_BOARD: &str = "main";
+
+fn boards_dir(db_path: &Path) -> PathBuf {
+    parent_dir(db_path).join("boards")
+}
+
+fn is_board_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json" || SQLITE_EXTENSIONS.iter().any(|sqlite| ext == *sqlite))
+}
+
+// Boards keep the backend they were created or migrated with, new ones follow the DB file
+pub fn board_path(db_path: &Path, name: &str) -> PathBuf {
+    if name == MAIN_BOARD {
+        return db_path.to_path_buf();
+    }
+    let dir = boards_dir(db_path);
+    let existing = fs::read_dir(&dir).into_iter().flatten().flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_board_file(path) && path.file_stem().is_some_and(|stem| stem == name))
+        .min();
+    existing.unwrap_or_else(|| {
+        let extension = db_path.extension().filter(|_| is_board_file(db_path)).unwrap_or("json".as_ref());
+        dir.join(name).with_extension(extension)
+    })
+}
+
+pub fn check_board_name(name: &str) -> Result<(), Error> {
+    let valid = name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if name.is_empty() || !valid {
+        return Err(Error::InvalidBoardName(name.to_string()));
+    }
+    Ok(())
+}
+
+// The main board first, then the others by name
+pub fn list_boards(db_path: &Path) -> io::Result<Vec<String>> {
+    let mut boards = vec![];
+    match fs::read_dir(boards_dir(db_path)) {
+        Ok(entries) => {
+            for entry in entries {
+                let path = entry?.path();
+                if is_board_file(&path) {
+                    boards.push(path.file_stem().unwrap_or_default().to_string_lossy().to_string());
+                }
+            }
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    boards.retain(|name| check_board_name(name).is_ok() && name != MAIN_BOARD);
+    boards.sort();
+    boards.dedup();
+    boards.insert(0, MAIN_BOARD.to_string());
+    Ok(boards)
+}
+
+// Files kept next to a board that belong to it: its undo history, activity log and todo.txt links
+const SIDECAR_SUFFIXES: [&str; 3] = [".undo", ".log", ".todotxt"];
+
+// Moves the board file together with its backups and sidecar files
+pub fn rename_board(db_path: &Path, from: &str, to: &str) -> Result<(), Error> {
+    check_board_name(to)?;
+    if from == MAIN_BOARD || to == MAIN_BOARD {
+        return Err(Error::MainBoard);
+    }
+    if list_boards(db_path)?.iter().any(|name| name == to) {
+        return Err(Error::BoardExists(to.to_string()));
+    }
+    let from_path = board_path(db_path, from);
+    if !from_path.exists() {
+        return Err(Error::NoSuchBoard(from.to_string()));
+    }
+    let to_path = from_path.with_file_name(to).with_extension(from_path.extension().unwrap_or_default());
+    fs::rename(&from_path, &to_path)?;
+    let from_prefix = from_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    for (_, backup) in list_backups(&from_path)? {
+        let name = backup.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let suffix = &name[from_prefix.len()..];
+        fs::rename(&backup, sibling_path(&to_path, suffix))?;
+    }
+    for suffix in SIDECAR_SUFFIXES {
+        let sidecar = sibling_path(&from_path, suffix);
+        if sidecar.exists() {
+            fs::rename(&sidecar, sibling_path(&to_path, suffix))?;
+        }
+    }
+    Ok(())
+}
+
+// Removes the board file, its undo history and todo.txt links after taking a final backup, so
+// `--board <name> restore` can bring it back. The activity log is kept as a record of what the board held
+pub fn delete_board(db_path: &Path, name: &str) -> Result<(), Error> {
+    if name == MAIN_BOARD {
+        return Err(Error::MainBoard);
+    }
+    let path = board_path(db_path, name);
+    if !path.exists() {
+        return Err(Error::NoSuchBoard(name.to_string()));
+    }
+    if fs::metadata(&path)?.len() > 0 {
+        force_backup(&path)?;
+    }
+    fs::remove_file(&path)?;
+    for suffix in [".undo", ".todotxt"] {
+        let sidecar = sibling_path(&path, suffix);
+        if sidecar.exists() {
+            fs::remove_file(sidecar)?;
+        }
+    }
+    Ok(())
+}
+
+/*** Reading and writing ***/
+// The directory a file is in, "." for a bare file name whose parent is the empty path
+pub fn parent_dir(path: &Path) -> &Path {
+    path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."))
+}
+
+// A file next to `path` with `suffix` added to its name, e.g. .data.json.v0.bak
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+pub fn read_db(db_path: &Path) -> Result<DbFile, Error> {
+    let db_content = match fs::read_to_string(db_path) {
+        Ok(db_content) =>{db_content}
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            if let Err(e) = fs::create_dir_all(parent_dir(db_path)) {
+                eprintln!("Failed to create directories: {}", e);
+                std::process::exit(1);
+            }
+            fs::File::create(db_path).expect("Failed to create JSON file");
+            "".to_string()
+        }
+        Err(error) => {
+            // Some other error occurred
+            eprintln!("Failed to read JSON file: {}", error);
+            std::process::exit(1);
+        }
+    };
+    if db_content.trim().is_empty() {
+        return Ok(DbFile { version: DB_VERSION, ..DbFile::default() });
+    }
+    let value: Value = serde_json::from_str(&db_content)?;
+
+    let version = file_version(&value);
+    if version > DB_VERSION {
+        return Err(Error::UnsupportedVersion(version, DB_VERSION));
+    }
+    if version == DB_VERSION {
+        // parse the text again so errors carry a line and column
+        return Ok(serde_json::from_str(&db_content)?);
+    }
+    // keep the file exactly as it was before upgrading it
+    fs::copy(db_path, sibling_path(db_path, &format!(".v{}.bak", version)))?;
+    Ok(serde_json::from_value(migrate(value, version))?)
+}
+
+// Brings a board envelope read from anywhere but a JSON file up to the current version
+pub fn upgrade(value: Value) -> Result<DbFile, Error> {
+    let version = file_version(&value);
+    if version > DB_VERSION {
+        return Err(Error::UnsupportedVersion(version, DB_VERSION));
+    }
+    Ok(serde_json::from_value(migrate(value, version))?)
+}
+
+pub fn write_db(db_path: &Path, db: &DbFile) -> Result<(), Error> {
+    backup_with(db_path, |backup_path| Ok(write_atomic(backup_path, &fs::read(db_path)?)?))?;
+    write_atomic(db_path, &serde_json::to_vec_pretty(db)?)?;
+    Ok(())
+}
+
+// Writes to a temporary file, syncs it and renames it over `path`, so a crash leaves
+// either the old or the new contents but never a mix
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp_path = sibling_path(path, ".tmp");
+    let mut tmp = fs::File::create(&tmp_path)?;
+    if let Err(err) = tmp.write_all(contents).and_then(|_| tmp.sync_all()) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+    fs::rename(&tmp_path, path)?;
+    // make the rename itself durable
+    if let Ok(dir) = fs::File::open(parent_dir(path)) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/*** Backups ***/
+// Existing backups of the DB file, newest first
+fn list_backups(db_path: &Path) -> io::Result<Vec<(NaiveDateTime, PathBuf)>> {
+    let prefix = format!("{}.backup-", db_path.file_name().unwrap_or_default().to_string_lossy());
+    let mut backups = vec![];
+    for entry in fs::read_dir(parent_dir(db_path))? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if let Some(time) = name.strip_prefix(&prefix).and_then(|time| NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).ok()) {
+            backups.push((time, path));
+        }
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.0));
+    Ok(backups)
+}
+
+fn new_backup_path(db_path: &Path) -> PathBuf {
+    sibling_path(db_path, &format!(".backup-{}", Local::now().format(BACKUP_TIME_FORMAT)))
+}
+
+// Unless the newest backup is recent, has `copy` write the current DB file to a new timestamped
+// backup and prunes the oldest ones. Backends copy their file the way that is safe for them
+pub fn backup_with(db_path: &Path, copy: impl FnOnce(&Path) -> Result<(), Error>) -> Result<(), Error> {
+    let now = Local::now().naive_local();
+    let backups = list_backups(db_path)?;
+    let recent = backups.first().is_some_and(|(time, _)| now - *time < Duration::minutes(BACKUP_INTERVAL_MINUTES));
+    let empty = fs::metadata(db_path).map(|meta| meta.len() == 0).unwrap_or(true);
+    if recent || empty {
+        return Ok(());
+    }
+    copy(&new_backup_path(db_path))?;
+    prune_backups(db_path)?;
+    Ok(())
+}
+
+fn force_backup(db_path: &Path) -> io::Result<()> {
+    write_atomic(&new_backup_path(db_path), &fs::read(db_path)?)?;
+    prune_backups(db_path)
+}
+
+fn prune_backups(db_path: &Path) -> io::Result<()> {
+    for (_, old_backup) in list_backups(db_path)?.into_iter().skip(BACKUP_COUNT) {
+        fs::remove_file(old_backup)?;
+    }
+    Ok(())
+}
+
+// `restore` lists the backups, `restore <n>` rolls the DB back to the n-th newest one
+pub fn restore_command(db_path: &Path, choice: Option<&str>) -> Result<(), Error> {
+    let backups = list_backups(db_path)?;
+    if backups.is_empty() {
+        println!("No backups of {} found", db_path.display());
+        return Ok(());
+    }
+
+    let Some(choice) = choice else {
+        println!("Backups of {} (newest first):", db_path.display());
+        for (i, (time, path)) in backups.iter().enumerate() {
+            let summary = match backup_size(db_path, path) {
+                Some((lists, tasks)) => format!("{} lists, {} tasks", lists, tasks),
+                None => "unreadable".to_string(),
+            };
+            println!("{:>3}  {}  {}", i + 1, time.format("%Y/%m/%d %H:%M:%S"), summary);
+        }
+        println!("Run `taskboardcli restore <number>` to roll back to one of them");
+        return Ok(());
+    };
+
+    let Some((time, backup_path)) = choice.parse::<usize>().ok().and_then(|n| backups.get(n.wrapping_sub(1))) else {
+        println!("No backup number {}, run `taskboardcli restore` to list them", choice);
+        return Ok(());
+    };
+    let contents = fs::read(backup_path)?;
+    // the current state becomes a backup too, so a restore can itself be undone
+    if fs::metadata(db_path).is_ok_and(|meta| meta.len() > 0) {
+        force_backup(db_path)?;
+    }
+    write_atomic(db_path, &contents)?;
+    println!("Restored {} from the backup taken {}", db_path.display(), time.format("%Y/%m/%d %H:%M:%S"));
+    Ok(())
+}
+
+// How many lists and tasks a backup holds, None if it cannot be read
+fn backup_size(db_path: &Path, backup_path: &Path) -> Option<(usize, usize)> {
+    if Backend::of(db_path) == Backend::Sqlite {
+        return sqlite::count_rows(backup_path).ok();
+    }
+    let value = serde_json::from_str::<Value>(&fs::read_to_string(backup_path).ok()?).ok()?;
+    let value = migrate(value.clone(), file_version(&value).min(DB_VERSION));
+    let lists = value.get("lists").and_then(Value::as_array).cloned().unwrap_or_default();
+    let tasks: usize = lists.iter().filter_map(|list| list.get("tasks").and_then(Value::as_array)).map(Vec::len).sum();
+    Some((lists.len(), tasks))
+}
+
+/*** Migrations ***/
+// Files from before the envelope are a bare array of lists
+fn file_version(value: &Value) -> u64 {
+    match value {
+        Value::Array(_) => 0,
+        _ => value.get("version").and_then(Value::as_u64).unwrap_or(0),
+    }
+}
+
+fn migrate(mut value: Value, from: u64) -> Value {
+    for step in MIGRATIONS.iter().skip(from as usize) {
+        value = step(value);
+    }
+    value
+}
+
+// v0 was a bare array of lists, originally numbered by position
+fn migrate_v0_to_v1(mut value: Value) -> Value {
+    if let Value::Array(lists) = &mut value {
+        for list in lists.iter_mut() {
+            if list.get("id").is_some_and(Value::is_number) {
+                list["id"] = Value::String(Uuid::new_v4().to_string());
+            }
+        }
+    }
+    serde_json::json!({
+        "version": 1,
+        "board": { "active_list": 1 },
+        "lists": value,
+    })
+}
+
+/*** Recovering a damaged DB file ***/
+/*
+* The result of salvaging a DB file that failed to parse
+* db: DbFile - Every list and task that could still be read
+* quarantine: PathBuf - Where the damaged file was moved to
+* lists, tasks: usize - How much was salvaged
+*/
+pub struct Recovery {
+    pub db: DbFile,
+    pub quarantine: PathBuf,
+    pub lists: usize,
+    pub tasks: usize,
+}
+
+// Moves the unreadable DB file aside, then keeps whatever lists and tasks still parse
+pub fn recover_db(db_path: &Path, error: &serde_json::Error) -> Result<Recovery, Error> {
+    let db_content = fs::read_to_string(db_path)?;
+    let quarantine = sibling_path(db_path, &format!(".corrupt-{}", Local::now().format("%Y%m%d-%H%M%S")));
+    fs::rename(db_path, &quarantine)?;
+
+    let value = serde_json::from_str::<Value>(&db_content).ok()
+        .or_else(|| repair_truncated(&db_content, error));
+    let mut db = DbFile { version: DB_VERSION, ..DbFile::default() };
+    if let Some(value) = value {
+        let version = file_version(&value);
+        let value = migrate(value, version.min(DB_VERSION));
+        if let Some(board) = value.get("board").and_then(|board| serde_json::from_value(board.clone()).ok()) {
+            db.board = board;
+        }
+        if let Some(lists) = value.get("lists").and_then(Value::as_array) {
+            db.lists = lists.iter().filter_map(salvage_list).collect();
+        }
+    }
+
+    let lists = db.lists.len();
+    let tasks = db.lists.iter().map(|list| list.tasks.len()).sum();
+    Ok(Recovery { db, quarantine, lists, tasks })
+}
+
+// Reads a list one task at a time, dropping only the tasks that do not parse
+fn salvage_list(value: &Value) -> Option<TaskList> {
+    let mut list_value = value.clone();
+    let tasks = list_value.get_mut("tasks").map(Value::take).unwrap_or_default();
+    list_value["tasks"] = Value::Array(vec![]);
+    let mut list: TaskList = match serde_json::from_value(list_value) {
+        Ok(list) => list,
+        Err(_) => {
+            let title = value.get("title").and_then(Value::as_str)?;
+            let now = Local::now();
+            TaskList {
+                id: Uuid::new_v4(),
+                title: title.to_string(),
+                tasks: vec![],
+                selected: 0,
+                hide_completed: false,
+                created_at: now,
+                updated_at: now,
+            }
+        }
+    };
+    if let Value::Array(tasks) = tasks {
+        list.tasks = tasks.into_iter().filter_map(|task| serde_json::from_value::<Task>(task).ok()).collect();
+    }
+    list.selected = 0;
+    Some(list)
+}
+
+// Cuts a syntactically broken file back to the last complete object before the error and
+// closes whatever arrays and objects are still open
+fn repair_truncated(content: &str, error: &serde_json::Error) -> Option<Value> {
+    // serde_json counts lines by '\n' alone and columns in bytes from 1, so a '\r' before the
+    // newline is part of the previous line and the offset may land inside a character
+    let line_start = match error.line() {
+        0 | 1 => 0,
+        line => content.match_indices('\n').nth(line - 2).map_or(content.len(), |(i, _)| i + 1),
+    };
+    let mut error_offset = (line_start + error.column().saturating_sub(1)).min(content.len());
+    while !content.is_char_boundary(error_offset) {
+        error_offset -= 1;
+    }
+    let prefix = &content[..error_offset];
+
+    let cut_points: Vec<usize> = prefix.char_indices().filter(|&(_, c)| c == '}').map(|(i, _)| i).collect();
+    for &cut in cut_points.iter().rev().take(200) {
+        let candidate = &prefix[..=cut];
+        let Some(closers) = open_brackets(candidate) else {
+            continue;
+        };
+        let repaired = format!("{}{}", candidate, closers.iter().rev().collect::<String>());
+        if let Ok(value) = serde_json::from_str(&repaired) {
+            return Some(value);
+        }
+    }
+    None
+}
+
+// The closing brackets needed to balance `content`, None if it ends inside a string
+fn open_brackets(content: &str) -> Option<Vec<char>> {
+    let mut stack = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in content.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => stack.push('}'),
+            '[' => stack.push(']'),
+            '}' | ']' => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+    (!in_string).then_some(stack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    // A board file as the baseline wrote it, before the envelope and ids
+    const V0_FILE: &str = r#"[{"id": 0, "title": "Work", "selected": 0, "tasks": [{"title": "Report", "date_string": "5/3", "due": "2024-05-03"}]},
+        {"id": 1, "title": "Home", "selected": 0, "tasks": []}]"#;
+
+    /*** Migrations ***/
+    #[test]
+    fn v0_files_are_wrapped_and_given_list_ids() {
+        let value = migrate_v0_to_v1(serde_json::from_str(V0_FILE).unwrap());
+        assert_eq!(file_version(&value), 1);
+        assert_eq!(value["board"]["active_list"], 1);
+        let ids: Vec<&str> = value["lists"].as_array().unwrap().iter().map(|list| list["id"].as_str().unwrap()).collect();
+        assert!(ids.iter().all(|id| Uuid::parse_str(id).is_ok()));
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn upgrade_reads_every_version_up_to_the_current_one() {
+        let db = upgrade(serde_json::from_str(V0_FILE).unwrap()).unwrap();
+        assert_eq!(db.version, DB_VERSION);
+        assert_eq!(db.lists.len(), 2);
+        assert_eq!(db.lists[0].tasks[0].title, "Report");
+
+        let current = serde_json::to_value(&db).unwrap();
+        assert_eq!(serde_json::to_value(upgrade(current.clone()).unwrap()).unwrap(), current);
+        let newer = serde_json::json!({ "version": DB_VERSION + 1, "lists": [] });
+        assert!(matches!(upgrade(newer), Err(Error::UnsupportedVersion(_, _))));
+    }
+
+    #[test]
+    fn read_db_keeps_a_copy_of_the_file_it_upgrades() {
+        let dir = temp_dir();
+        let path = dir.join("board.json");
+        fs::write(&path, V0_FILE).unwrap();
+        let db = read_db(&path).unwrap();
+        assert_eq!(db.lists.len(), 2);
+        assert_eq!(fs::read_to_string(sibling_path(&path, ".v0.bak")).unwrap(), V0_FILE);
+    }
+
+    /*** Locating the DB file ***/
+    #[test]
+    fn relative_db_paths_are_made_absolute() {
+        let (path, note) = resolve_db_path(Some(PathBuf::from("board.json"))).unwrap();
+        assert_eq!(path, std::env::current_dir().unwrap().join("board.json"));
+        assert!(note.is_none());
+        let (path, _) = resolve_db_path(Some(PathBuf::from("/tmp/board.json"))).unwrap();
+        assert_eq!(path, Path::new("/tmp/board.json"));
+    }
+
+    /*** Saving and backups ***/
+    #[test]
+    fn bare_file_names_live_in_the_working_directory() {
+        // as with `--db board.json`, nothing is written here
+        let path = Path::new("board.json");
+        assert_eq!(parent_dir(path), Path::new("."));
+        assert_eq!(boards_dir(path), Path::new(".").join("boards"));
+        assert_eq!(board_path(path, "Home"), Path::new(".").join("boards").join("Home.json"));
+        assert_eq!(parent_dir(&new_backup_path(path)), Path::new("."));
+        assert_eq!(parent_dir(&sibling_path(path, ".undo")), Path::new("."));
+        assert!(list_backups(path).is_ok());
+    }
+
+    #[test]
+    fn saves_keep_a_backup_next_to_the_file() {
+        let dir = temp_dir();
+        let path = dir.join("board.json");
+        let db = DbFile { version: DB_VERSION, lists: vec![crate::transfer::new_list("Work")], ..DbFile::default() };
+        write_db(&path, &db).and_then(|_| write_db(&path, &db)).unwrap();
+        assert_eq!(read_db(&path).unwrap().lists.len(), 1);
+        let backups = list_backups(&path).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(parent_dir(&backups[0].1), dir.path());
+    }
+
+    /*** Boards ***/
+    // A DB file in a fresh directory with one more board "Side" that has a backup and sidecar files
+    fn board_with_sidecars(dir: &Path) -> (PathBuf, PathBuf, String) {
+        let db_path = dir.join("board.json");
+        let side = board_path(&db_path, "Side");
+        let content = board_file();
+        fs::create_dir_all(boards_dir(&db_path)).unwrap();
+        fs::write(&side, &content).unwrap();
+        force_backup(&side).unwrap();
+        for suffix in SIDECAR_SUFFIXES {
+            fs::write(sibling_path(&side, suffix), suffix).unwrap();
+        }
+        (db_path, side, content)
+    }
+
+    #[test]
+    fn rename_board_carries_backups_and_sidecars() {
+        let dir = temp_dir();
+        let (db_path, side, content) = board_with_sidecars(dir.path());
+        rename_board(&db_path, "Side", "Other").unwrap();
+
+        let other = board_path(&db_path, "Other");
+        assert_eq!(other, boards_dir(&db_path).join("Other.json"));
+        assert_eq!(fs::read_to_string(&other).unwrap(), content);
+        assert!(!side.exists());
+        assert_eq!(list_backups(&other).unwrap().len(), 1);
+        assert!(list_backups(&side).unwrap().is_empty());
+        for suffix in SIDECAR_SUFFIXES {
+            assert_eq!(fs::read_to_string(sibling_path(&other, suffix)).unwrap(), suffix);
+            assert!(!sibling_path(&side, suffix).exists());
+        }
+        assert_eq!(list_boards(&db_path).unwrap(), [MAIN_BOARD, "Other"]);
+    }
+
+    #[test]
+    fn rename_board_refuses_taken_names_and_the_main_board() {
+        let dir = temp_dir();
+        let (db_path, side, _) = board_with_sidecars(dir.path());
+        fs::write(board_path(&db_path, "Other"), board_file()).unwrap();
+        assert!(matches!(rename_board(&db_path, "Side", "Other"), Err(Error::BoardExists(_))));
+        assert!(matches!(rename_board(&db_path, "Side", MAIN_BOARD), Err(Error::MainBoard)));
+        assert!(matches!(rename_board(&db_path, "Gone", "New"), Err(Error::NoSuchBoard(_))));
+        assert!(side.exists());
+    }
+
+    #[test]
+    fn deleted_boards_can_be_restored() {
+        let dir = temp_dir();
+        let (db_path, side, content) = board_with_sidecars(dir.path());
+        delete_board(&db_path, "Side").unwrap();
+
+        assert!(!side.exists());
+        assert!(!sibling_path(&side, ".undo").exists());
+        assert!(!sibling_path(&side, ".todotxt").exists());
+        assert!(sibling_path(&side, ".log").exists());
+        assert_eq!(list_boards(&db_path).unwrap(), [MAIN_BOARD]);
+
+        // the board path is the same once the file is gone, so restore finds the backup
+        assert_eq!(board_path(&db_path, "Side"), side);
+        restore_command(&side, Some("1")).unwrap();
+        assert_eq!(fs::read_to_string(&side).unwrap(), content);
+        assert_eq!(list_boards(&db_path).unwrap(), [MAIN_BOARD, "Side"]);
+    }
+
+    /*** Recovering a damaged DB file ***/
+    fn syntax_error(content: &str) -> serde_json::Error {
+        serde_json::from_str::<Value>(content).unwrap_err()
+    }
+
+    // A board file with two lists of two tasks, pretty printed the way write_db does it
+    fn board_file() -> String {
+        let lists = ["Work", "Home"].iter().map(|title| {
+            let mut list = crate::transfer::new_list(title);
+            list.tasks = vec![Task::new(format!("{} one", title)), Task::new(format!("{} two", title))];
+            list
+        }).collect();
+        serde_json::to_string_pretty(&DbFile { version: DB_VERSION, lists, ..DbFile::default() }).unwrap()
+    }
+
+    #[test]
+    fn repair_truncated_keeps_complete_items() {
+        let content = board_file();
+        let truncated = &content[..content.find("Home two").unwrap()];
+        let value = repair_truncated(truncated, &syntax_error(truncated)).unwrap();
+        let lists = value["lists"].as_array().unwrap();
+        assert_eq!(lists.len(), 2);
+        assert_eq!(lists[0]["tasks"].as_array().unwrap().len(), 2);
+        assert_eq!(lists[1]["tasks"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn repair_truncated_reads_crlf_files() {
+        let content = board_file().replace('\n', "\r\n");
+        let truncated = &content[..content.find("Home two").unwrap()];
+        let value = repair_truncated(truncated, &syntax_error(truncated)).unwrap();
+        assert_eq!(value["lists"][1]["tasks"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn repair_truncated_never_panics_inside_a_character() {
+        for content in [
+            r#"{"version": 1, "lists": [ é ]}"#,
+            "{\"version\": 1,\r\n \"lists\": [ \"ü\" ✓ ]}",
+            "{\"title\": \"日本語\", \"tasks\": [{\"title\": \"é\"}, é",
+        ] {
+            repair_truncated(content, &syntax_error(content));
+        }
+    }
+
+    #[test]
+    fn recover_db_moves_the_file_aside_and_salvages_tasks() {
+        let dir = temp_dir();
+        let path = dir.join("board.json");
+        let content = board_file();
+        // one task of the first list no longer parses, the second list is cut off
+        let damaged = content.replacen("\"Work two\"", "42", 1);
+        let damaged = &damaged[..damaged.find("Home two").unwrap()];
+        fs::write(&path, damaged).unwrap();
+
+        let recovery = recover_db(&path, &syntax_error(damaged)).unwrap();
+        assert_eq!((recovery.lists, recovery.tasks), (2, 2));
+        assert_eq!(recovery.db.lists[0].tasks[0].title, "Work one");
+        assert_eq!(recovery.db.lists[1].tasks[0].title, "Home one");
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(&recovery.quarantine).unwrap(), damaged);
+    }
+}
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::activity::{list_events, task_events, Event};
+use crate::db::write_atomic;
+use crate::{fix_selection, Error, Task, TaskBoard, TaskList};
+
+// Undo steps kept per board, the oldest are dropped first
+const HISTORY_LIMIT: usize = 100;
+
+/*
+* Undo and redo stacks of a board
+* undo: Vec<Step> - Steps that can be undone, newest last
+* redo: Vec<Step> - Undone steps that can be redone, most recently undone last
+* base: Option<Vec<TaskList>> - The lists as of the last recorded step, None until the board is loaded
+* applied: bool - Whether the last key press undid or redid a step, so the lists it put back are not stamped
+*/
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    #[serde(skip)]
+    base: Option<Vec<TaskList>>,
+    #[serde(skip)]
+    applied: bool,
+}
+
+/*
+* Everything that changed between two visits to Home
+* summary: String - What the step did, shown when it is undone or redone
+* changes: Vec<Change> - Lists added or edited first, then tasks, then lists removed
+*/
+#[derive(Serialize, Deserialize, Clone)]
+struct Step {
+    summary: String,
+    changes: Vec<Change>,
+}
+
+/*
+* A single list or task before and after a step, None where it did not exist
+* List - The list without its tasks, and its position on the board
+* Task - The task with the list it was in and its position there
+*/
+#[derive(Serialize, Deserialize, Clone)]
+enum Change {
+    List { index: usize, before: Option<Box<TaskList>>, after: Option<Box<TaskList>> },
+    Task { before: Option<Box<TaskPlace>>, after: Option<Box<TaskPlace>> },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct TaskPlace {
+    list: Uuid,
+    index: usize,
+    task: Task,
+}
+
+impl History {
+    // Records whatever changed since the last call as one step and returns it as activity events
+    pub fn record(&mut self, lists: &[TaskList]) -> Vec<Event> {
+        let Some(base) = &self.base else {
+            self.base = Some(lists.to_vec());
+            return vec![];
+        };
+        let changes = diff(base, lists);
+        if changes.is_empty() {
+            return vec![];
+        }
+        let events = changes.iter().flat_map(|change| change_events(change, false)).collect();
+        self.undo.push(Step { summary: summarize(&changes), changes });
+        if self.undo.len() > HISTORY_LIMIT {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        self.base = Some(lists.to_vec());
+        events
+    }
+
+    // Takes `lists` as the recorded state without making a step, e.g. after loading the board or
+    // merging in another instance's changes, so only what the user does next ends up in a step
+    pub fn resync(&mut self, lists: &[TaskList]) {
+        self.base = Some(lists.to_vec());
+    }
+
+    // Whether undo or redo changed the lists since the last call. Their tasks keep the timestamps
+    // of the step, and stamping them as edited would record the undo as a new step and drop the redo stack
+    pub fn take_applied(&mut self) -> bool {
+        std::mem::take(&mut self.applied)
+    }
+}
+
+pub fn read_history(path: &Path) -> History {
+    fs::read_to_string(path).ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub fn write_history(path: &Path, history: &History) -> Result<(), Error> {
+    write_atomic(path, &serde_json::to_vec(history)?)?;
+    Ok(())
+}
+
+/*** Undo and redo ***/
+pub fn undo(taskboard: &mut TaskBoard) {
+    let Some(step) = taskboard.history.undo.pop() else {
+        taskboard.debug_str = "Nothing to undo".to_string();
+        return;
+    };
+    for change in step.changes.iter().rev() {
+        apply(&mut taskboard.lists, change, true);
+        taskboard.activity.extend(change_events(change, true).into_iter().map(|event| Event { detail: by("undo", event.detail), ..event }));
+    }
+    taskboard.debug_str = format!("Undid {} (U to redo)", step.summary);
+    taskboard.history.redo.push(step);
+    after_apply(taskboard);
+}
+
+pub fn redo(taskboard: &mut TaskBoard) {
+    let Some(step) = taskboard.history.redo.pop() else {
+        taskboard.debug_str = "Nothing to redo".to_string();
+        return;
+    };
+    for change in step.changes.iter() {
+        apply(&mut taskboard.lists, change, false);
+        taskboard.activity.extend(change_events(change, false).into_iter().map(|event| Event { detail: by("redo", event.detail), ..event }));
+    }
+    taskboard.debug_str = format!("Redid {}", step.summary);
+    taskboard.history.undo.push(step);
+    after_apply(taskboard);
+}
+
+fn after_apply(taskboard: &mut TaskBoard) {
+    taskboard.num_lists = taskboard.lists.len();
+    taskboard.active_list = taskboard.active_list.clamp(1, taskboard.num_lists.max(1));
+    for list in taskboard.lists.iter_mut() {
+        fix_selection(list, &taskboard.tag_filter);
+    }
+    taskboard.history.resync(&taskboard.lists);
+    taskboard.history.applied = true;
+}
+
+// Puts a list or task into its state after the change, or before it when undoing. Items are
+// found by id, so the step still applies after tasks were re-sorted or moved
+fn apply(lists: &mut Vec<TaskList>, change: &Change, undo: bool) {
+    match change {
+        Change::List { index, before, after } => {
+            let (from, to) = if undo { (after, before) } else { (before, after) };
+            let Some(id) = from.as_ref().or(to.as_ref()).map(|list| list.id) else {
+                return;
+            };
+            match (to, lists.iter().position(|list| list.id == id)) {
+                (None, Some(i)) => {
+                    lists.remove(i);
+                }
+                (Some(header), Some(i)) => {
+                    let tasks = std::mem::take(&mut lists[i].tasks);
+                    lists[i] = TaskList { tasks, selected: lists[i].selected, ..(**header).clone() };
+                }
+                (Some(header), None) => lists.insert((*index).min(lists.len()), (**header).clone()),
+                (None, None) => {}
+            }
+        }
+        Change::Task { before, after } => {
+            let (from, to) = if undo { (after, before) } else { (before, after) };
+            let Some(id) = from.as_ref().or(to.as_ref()).map(|place| place.task.id) else {
+                return;
+            };
+            for list in lists.iter_mut() {
+                list.tasks.retain(|task| task.id != id);
+            }
+            if let Some(place) = to {
+                if let Some(list) = lists.iter_mut().find(|list| list.id == place.list) {
+                    list.tasks.insert(place.index.min(list.tasks.len()), place.task.clone());
+                }
+            }
+        }
+    }
+}
+
+/*** Activity ***/
+fn change_events(change: &Change, undo: bool) -> Vec<Event> {
+    match change {
+        Change::List { before, after, .. } => {
+            let (from, to) = if undo { (after, before) } else { (before, after) };
+            list_events(from.as_deref(), to.as_deref())
+        }
+        Change::Task { before, after } => {
+            let (from, to) = if undo { (after, before) } else { (before, after) };
+            task_events(from.as_ref().map(|place| (&place.task, place.list)), to.as_ref().map(|place| (&place.task, place.list)))
+        }
+    }
+}
+
+fn by(command: &str, detail: String) -> String {
+    match detail.is_empty() {
+        true => format!("by {}", command),
+        false => format!("{}, by {}", detail, command),
+    }
+}
+
+/*** Recording ***/
+fn list_header(list: &TaskList) -> TaskList {
+    TaskList { tasks: vec![], selected: 0, ..list.clone() }
+}
+
+// Lists count as edited when their title or hide setting changed, tasks when stamp_changes
+// bumped them, so relabelled due dates and re-sorting never make a step
+fn diff(before: &[TaskList], after: &[TaskList]) -> Vec<Change> {
+    let mut changes = vec![];
+    let mut removed_lists = vec![];
+    for (index, list) in after.iter().enumerate() {
+        match before.iter().find(|old| old.id == list.id) {
+            None => changes.push(Change::List { index, before: None, after: Some(Box::new(list_header(list))) }),
+            Some(old) if old.title != list.title || old.hide_completed != list.hide_completed => {
+                changes.push(Change::List { index, before: Some(Box::new(list_header(old))), after: Some(Box::new(list_header(list))) });
+            }
+            Some(_) => {}
+        }
+    }
+    for (index, old) in before.iter().enumerate() {
+        if !after.iter().any(|list| list.id == old.id) {
+            removed_lists.push(Change::List { index, before: Some(Box::new(list_header(old))), after: None });
+        }
+    }
+
+    let places = |lists: &[TaskList]| -> HashMap<Uuid, TaskPlace> {
+        lists.iter()
+            .flat_map(|list| list.tasks.iter().enumerate().map(|(index, task)| (task.id, TaskPlace { list: list.id, index, task: task.clone() })))
+            .collect()
+    };
+    let (old_places, new_places) = (places(before), places(after));
+    for list in after {
+        for task in list.tasks.iter() {
+            let place = &new_places[&task.id];
+            match old_places.get(&task.id) {
+                None => changes.push(Change::Task { before: None, after: Some(Box::new(place.clone())) }),
+                Some(old) if old.task.updated_at != task.updated_at || old.list != place.list => {
+                    changes.push(Change::Task { before: Some(Box::new(old.clone())), after: Some(Box::new(place.clone())) });
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    for list in before {
+        for task in list.tasks.iter() {
+            if !new_places.contains_key(&task.id) {
+                changes.push(Change::Task { before: Some(Box::new(old_places[&task.id].clone())), after: None });
+            }
+        }
+    }
+
+    changes.extend(removed_lists);
+    changes
+}
+
+// Names the most drastic change of a step, e.g. `delete list "Groceries" and 4 more changes`
+fn summarize(changes: &[Change]) -> String {
+    let describe = |change: &Change| -> (u8, String) {
+        let (rank, verb, kind, title) = match change {
+            Change::List { before: Some(list), after: None, .. } => (5, "delete", "list", list.title.as_str()),
+            Change::List { before: None, after: Some(list), .. } => (4, "add", "list", list.title.as_str()),
+            Change::List { after: Some(list), .. } => (1, "edit", "list", list.title.as_str()),
+            Change::Task { before: Some(place), after: None } => (3, "delete", "task", place.task.title.as_str()),
+            Change::Task { before: None, after: Some(place) } => (2, "add", "task", place.task.title.as_str()),
+            Change::Task { after: Some(place), .. } => (0, "edit", "task", place.task.title.as_str()),
+            _ => (0, "change", "item", ""),
+        };
+        (rank, format!("{} {} \"{}\"", verb, kind, title))
+    };
+    let (_, main) = changes.iter().map(describe).max_by_key(|(rank, _)| *rank).unwrap_or_default();
+    match changes.len() {
+        0 | 1 => main,
+        2 => format!("{} and 1 more change", main),
+        n => format!("{} and {} more changes", main, n - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::*;
+    use crate::db::DbFile;
+    use crate::testing::{list, titles};
+
+    fn board(lists: Vec<TaskList>) -> TaskBoard {
+        crate::new_taskboard(DbFile { lists, ..DbFile::default() }, "main".to_string(), String::new(), History::default())
+    }
+
+    // Handles a key press on Home the way `run` does: stamps what it changed and records the step
+    fn press(taskboard: &mut TaskBoard, key: impl FnOnce(&mut TaskBoard)) -> Vec<Event> {
+        let before = taskboard.lists.clone();
+        key(taskboard);
+        if !taskboard.history.take_applied() {
+            crate::stamp_changes(&before, &mut taskboard.lists);
+        }
+        taskboard.history.record(&taskboard.lists)
+    }
+
+    fn edit(taskboard: &mut TaskBoard, change: impl FnOnce(&mut Vec<TaskList>)) -> Vec<Event> {
+        press(taskboard, |taskboard| change(&mut taskboard.lists))
+    }
+
+    #[test]
+    fn diff_finds_added_edited_and_removed_items() {
+        let before = vec![list("Work", &["Report", "Review"])];
+        let mut after = before.clone();
+        after[0].tasks[0].title = "Final report".to_string();
+        after[0].tasks[0].updated_at = Local::now();
+        after[0].tasks.remove(1);
+        after[0].tasks.push(Task::new("Deck".to_string()));
+        after.push(list("Home", &[]));
+        let changes = diff(&before, &after);
+        assert_eq!(changes.len(), 4);
+        assert_eq!(summarize(&changes), "add list \"Home\" and 3 more changes");
+        assert!(diff(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn diff_ignores_relabelled_dates_and_sorting() {
+        let before = vec![list("Work", &["Report", "Review"])];
+        let mut after = before.clone();
+        after[0].tasks[0].date_string = "Tomorrow".to_string();
+        after[0].tasks.swap(0, 1);
+        assert!(diff(&before, &after).is_empty());
+    }
+
+    #[test]
+    fn undo_and_redo_apply_a_step_both_ways() {
+        let mut taskboard = board(vec![list("Work", &["Report", "Review"])]);
+        let original = titles(&taskboard.lists);
+        let events = edit(&mut taskboard, |lists| {
+            lists[0].tasks.remove(0);
+            lists[0].tasks[0].title = "Code review".to_string();
+            lists.push(list("Home", &["Laundry"]));
+        });
+        assert!(!events.is_empty());
+        let edited = titles(&taskboard.lists);
+
+        undo(&mut taskboard);
+        assert_eq!(titles(&taskboard.lists), original);
+        redo(&mut taskboard);
+        assert_eq!(titles(&taskboard.lists), edited);
+        undo(&mut taskboard);
+        assert_eq!(titles(&taskboard.lists), original);
+    }
+
+    #[test]
+    fn undo_and_redo_keys_walk_through_several_steps() {
+        let mut taskboard = board(vec![list("Work", &[])]);
+        let start = titles(&taskboard.lists);
+        edit(&mut taskboard, |lists| lists[0].tasks.push(Task::new("Report".to_string())));
+        let one = titles(&taskboard.lists);
+        edit(&mut taskboard, |lists| lists[0].tasks.push(Task::new("Review".to_string())));
+
+        // the lists put back are not taken for a new edit, so each undo goes one step further
+        assert!(press(&mut taskboard, undo).is_empty());
+        assert_eq!(titles(&taskboard.lists), one);
+        assert!(press(&mut taskboard, undo).is_empty());
+        assert_eq!(titles(&taskboard.lists), start);
+        assert!(press(&mut taskboard, redo).is_empty());
+        assert_eq!(titles(&taskboard.lists), one);
+        assert_eq!((taskboard.history.undo.len(), taskboard.history.redo.len()), (1, 1));
+    }
+
+    #[test]
+    fn changes_after_a_resync_are_recorded() {
+        let mut taskboard = board(vec![list("Work", &["Report"])]);
+        // another instance's change merged in
+        taskboard.lists[0].tasks.push(Task::new("Theirs".to_string()));
+        taskboard.history.resync(&taskboard.lists);
+
+        let events = edit(&mut taskboard, |lists| lists[0].tasks.push(Task::new("Mine".to_string())));
+        assert_eq!(events.len(), 1);
+        press(&mut taskboard, undo);
+        assert_eq!(titles(&taskboard.lists), vec![("Work".to_string(), vec!["Report".to_string(), "Theirs".to_string()])]);
+
+        // the next change after an undo is recorded too
+        let events = edit(&mut taskboard, |lists| lists[0].tasks[0].title = "Draft".to_string());
+        assert_eq!(events.len(), 1);
+    }
+}
@@ -0,0 +1,497 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use uuid::Uuid;
+
+use crate::transfer::{list_index, set_done, set_due, Field, DEFAULT_LIST};
+use crate::{Error, Priority, Task, TaskList};
+
+/*** iCalendar ***/
+// Tasks with a due date become VTODO entries, or all-day VEVENT entries for calendars that do not
+// show to-dos. UIDs are the task ids, so a calendar that subscribes to the file sees updates rather
+// than new entries, and importing the file back finds the same tasks
+pub const FIELDS: &[Field] = &[Field::Due, Field::Done, Field::Notes, Field::Priority, Field::Tags];
+
+const UID_DOMAIN: &str = "@taskboardcli";
+// Remember the list a task came from so importing the file back sorts it in again, and when an
+// event's task was finished since events have no completion of their own
+const LIST_PROPERTY: &str = "X-TASKBOARD-LIST";
+const EVENT_COMPLETED_PROPERTY: &str = "X-TASKBOARD-COMPLETED";
+
+/*** Writing ***/
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+// Lines longer than 75 bytes are folded onto continuation lines that start with a space
+fn push_line(out: &mut String, line: &str) {
+    let mut start = 0;
+    let mut width = 75;
+    while line.len() - start > width {
+        let mut end = start + width;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        out.push_str(&line[start..end]);
+        out.push_str("\r\n ");
+        start = end;
+        width = 74;
+    }
+    out.push_str(&line[start..]);
+    out.push_str("\r\n");
+}
+
+fn utc_stamp(time: DateTime<Local>) -> String {
+    time.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn date_value(date: NaiveDate) -> String {
+    format!("VALUE=DATE:{}", date.format("%Y%m%d"))
+}
+
+// 1 is the highest priority in iCalendar, 0 means none
+fn ical_priority(priority: Priority) -> u8 {
+    match priority {
+        Priority::None => 0,
+        Priority::Urgent => 1,
+        Priority::High => 3,
+        Priority::Medium => 5,
+        Priority::Low => 7,
+    }
+}
+
+fn task_priority(priority: u8) -> Priority {
+    match priority {
+        0 => Priority::None,
+        1 => Priority::Urgent,
+        2..=4 => Priority::High,
+        5 => Priority::Medium,
+        _ => Priority::Low,
+    }
+}
+
+pub fn export(lists: &[TaskList], events: bool) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//taskboardcli//EN");
+    let now = Local::now();
+    for list in lists {
+        for task in list.tasks.iter().filter(|task| !task.date_string.is_empty()) {
+            let component = if events { "VEVENT" } else { "VTODO" };
+            let mut lines = vec![
+                format!("BEGIN:{}", component),
+                format!("UID:{}{}", task.id, UID_DOMAIN),
+                format!("DTSTAMP:{}", utc_stamp(now)),
+                format!("CREATED:{}", utc_stamp(task.created_at)),
+                format!("LAST-MODIFIED:{}", utc_stamp(task.updated_at)),
+                format!("SUMMARY:{}", escape(&task.title)),
+            ];
+            if events {
+                lines.push(format!("DTSTART;{}", date_value(task.due)));
+                lines.push(format!("DTEND;{}", date_value(task.due.succ_opt().unwrap_or(task.due))));
+                lines.push("TRANSP:TRANSPARENT".to_string());
+                if let (true, Some(completed_at)) = (task.done, task.completed_at) {
+                    lines.push(format!("{}:{}", EVENT_COMPLETED_PROPERTY, utc_stamp(completed_at)));
+                }
+            } else {
+                lines.push(match task.due_at {
+                    Some(due_at) => format!("DUE:{}", utc_stamp(due_at)),
+                    None => format!("DUE;{}", date_value(task.due)),
+                });
+                lines.push(format!("STATUS:{}", if task.done { "COMPLETED" } else { "NEEDS-ACTION" }));
+                if let (true, Some(completed_at)) = (task.done, task.completed_at) {
+                    lines.push(format!("COMPLETED:{}", utc_stamp(completed_at)));
+                }
+            }
+            if task.priority != Priority::None {
+                lines.push(format!("PRIORITY:{}", ical_priority(task.priority)));
+            }
+            if !task.tags.is_empty() {
+                lines.push(format!("CATEGORIES:{}", task.tags.iter().map(|tag| escape(tag)).collect::<Vec<_>>().join(",")));
+            }
+            if !task.notes.is_empty() {
+                lines.push(format!("DESCRIPTION:{}", escape(&task.notes)));
+            }
+            lines.push(format!("{}:{}", LIST_PROPERTY, escape(&list.title)));
+            lines.push(format!("END:{}", component));
+            for line in lines {
+                push_line(&mut out, &line);
+            }
+        }
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/*** Reading ***/
+/*
+* One content line, e.g. `DUE;VALUE=DATE:20240503`
+* name: String - Property name, upper case
+* params: Vec<(String, String)> - Parameters with upper case names
+* value: String - Raw value, still escaped
+*/
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+// Commas separate values unless escaped
+fn split_list(text: &str) -> Vec<String> {
+    let mut values = vec![String::new()];
+    let mut escaped = false;
+    for c in text.chars() {
+        match (c, escaped) {
+            (',', false) => values.push(String::new()),
+            ('\\', false) => escaped = true,
+            (c, _) => {
+                values.last_mut().unwrap().push(c);
+                escaped = false;
+            }
+        }
+    }
+    values.into_iter().map(|value| value.trim().to_string()).filter(|value| !value.is_empty()).collect()
+}
+
+fn parse_property(line: &str) -> Option<Property> {
+    // the value starts at the first colon outside a quoted parameter value
+    let mut quoted = false;
+    let colon = line.char_indices().find(|&(_, c)| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        c == ':' && !quoted
+    })?.0;
+    let mut parts = line[..colon].split(';');
+    let name = parts.next()?.to_uppercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.to_uppercase(), value.trim_matches('"').to_string()))
+        .collect();
+    Some(Property { name, params, value: line[colon + 1..].to_string() })
+}
+
+/*** Time zones ***/
+// Month, week and weekday of a yearly rule
+type Yearly = (u32, i8, Weekday);
+
+/*
+* One STANDARD or DAYLIGHT part of a VTIMEZONE
+* start: NaiveDateTime - When the part first applies, in the zone's own time
+* offset: i32 - Seconds ahead of UTC while it applies (TZOFFSETTO)
+* yearly: Option<Yearly> - Month, week and weekday it starts on again each year, such as
+*     (3, -1, Sun) for the last Sunday in March. None when it applies only from `start` on
+*/
+struct ZoneRule {
+    start: NaiveDateTime,
+    offset: i32,
+    yearly: Option<Yearly>,
+}
+
+fn parse_stamp(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.trim(), "%Y%m%dT%H%M%S").ok()
+}
+
+// +0100, -0530 or +013000
+fn parse_offset(value: &str) -> Option<i32> {
+    let value = value.trim();
+    let sign = match value.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits = value.get(1..).filter(|digits| matches!(digits.len(), 4 | 6) && digits.bytes().all(|b| b.is_ascii_digit()))?;
+    let part = |range: std::ops::Range<usize>| digits.get(range).map_or(0, |part| part.parse::<i32>().unwrap_or(0));
+    Some(sign * (part(0..2) * 3600 + part(2..4) * 60 + part(4..6)))
+}
+
+// Only the yearly rules time zones are written with are understood, e.g. FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU
+fn parse_yearly(rule: &str) -> Option<Yearly> {
+    let parts: HashMap<&str, &str> = rule.split(';').filter_map(|part| part.split_once('=')).collect();
+    if parts.get("FREQ") != Some(&"YEARLY") || parts.keys().any(|key| !matches!(*key, "FREQ" | "BYMONTH" | "BYDAY" | "UNTIL")) {
+        return None;
+    }
+    let month = parts.get("BYMONTH")?.parse().ok().filter(|month| (1..=12).contains(month))?;
+    let day = parts.get("BYDAY")?;
+    let week = day.trim_end_matches(char::is_alphabetic).parse().ok().filter(|week: &i8| matches!(week, -5..=-1 | 1..=5))?;
+    let weekday = match &day[day.len().checked_sub(2)?..] {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    };
+    Some((month, week, weekday))
+}
+
+// The given weekday of a month, counting from its end when `week` is negative
+fn weekday_of_month(year: i32, month: u32, week: i8, weekday: Weekday) -> Option<NaiveDate> {
+    if week > 0 {
+        return NaiveDate::from_weekday_of_month_opt(year, month, weekday, week as u8);
+    }
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let last = first.checked_add_months(chrono::Months::new(1))?.pred_opt()?;
+    let back = (last.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+    let date = last - Duration::days(back as i64 + 7 * (-week as i64 - 1));
+    Some(date).filter(|date| date.month() == month)
+}
+
+// Reads every VTIMEZONE in the file by its TZID. Zones with rules that are not understood are left
+// out, so times in them are refused rather than shifted
+fn time_zones(lines: &[String]) -> HashMap<String, Vec<ZoneRule>> {
+    let mut zones = HashMap::new();
+    let mut zone: Option<(String, Vec<ZoneRule>, bool)> = None;
+    let mut rule: Option<(Option<NaiveDateTime>, Option<i32>, Option<Yearly>)> = None;
+    for property in lines.iter().filter_map(|line| parse_property(line)) {
+        let value = property.value.trim().to_uppercase();
+        match (property.name.as_str(), &mut zone, &mut rule) {
+            ("BEGIN", None, _) if value == "VTIMEZONE" => zone = Some((String::new(), vec![], true)),
+            ("BEGIN", Some(_), None) if value == "STANDARD" || value == "DAYLIGHT" => rule = Some((None, None, None)),
+            ("TZID", Some((id, _, _)), None) => *id = property.value.trim().to_string(),
+            ("DTSTART", Some(_), Some((start, _, _))) => *start = parse_stamp(&property.value),
+            ("TZOFFSETTO", Some(_), Some((_, offset, _))) => *offset = parse_offset(&property.value),
+            ("RRULE", Some((_, _, understood)), Some((_, _, yearly))) => match parse_yearly(&value) {
+                Some(parsed) => *yearly = Some(parsed),
+                None => *understood = false,
+            },
+            ("END", Some((_, rules, understood)), Some(_)) => match rule.take() {
+                Some((Some(start), Some(offset), yearly)) => rules.push(ZoneRule { start, offset, yearly }),
+                _ => *understood = false,
+            },
+            ("END", Some(_), None) if value == "VTIMEZONE" => {
+                if let Some((id, rules, true)) = zone.take().filter(|(_, rules, _)| !rules.is_empty()) {
+                    zones.insert(id, rules);
+                }
+                zone = None;
+            }
+            _ => {}
+        }
+    }
+    zones
+}
+
+// The offset of the part that began last before `time`, or of the earliest part for times before them all
+fn zone_offset(rules: &[ZoneRule], time: NaiveDateTime) -> i32 {
+    let onsets = rules.iter().flat_map(|rule| {
+        let yearly = rule.yearly.map(|(month, week, weekday)| {
+            [time.year() - 1, time.year()].into_iter()
+                .filter_map(move |year| weekday_of_month(year, month, week, weekday))
+                .map(|date| date.and_time(rule.start.time()))
+                .filter(|onset| *onset >= rule.start)
+        });
+        yearly.into_iter().flatten().chain([rule.start]).map(|onset| (onset, rule.offset))
+    });
+    onsets.filter(|(onset, _)| *onset <= time)
+        .max_by_key(|(onset, _)| *onset)
+        .or(rules.iter().min_by_key(|rule| rule.start).map(|rule| (rule.start, rule.offset)))
+        .map_or(0, |(_, offset)| offset)
+}
+
+// A DATE, a UTC DATE-TIME ending in Z, one in a zone the file describes or a floating one, which is
+// read as local time
+fn parse_time(property: &Property, zones: &HashMap<String, Vec<ZoneRule>>) -> Result<(NaiveDate, Option<DateTime<Local>>), String> {
+    let value = property.value.trim();
+    let unreadable = || format!("cannot read the date \"{}\"", value);
+    let date_only = property.params.iter().any(|(key, value)| key == "VALUE" && value == "DATE");
+    if date_only || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d").map(|date| (date, None)).map_err(|_| unreadable());
+    }
+    let tzid = property.params.iter().find(|(key, _)| key == "TZID").map(|(_, tzid)| tzid.as_str());
+    let (time, utc) = match value.strip_suffix('Z') {
+        Some(time) => (time, true),
+        None => (value, matches!(tzid, Some("UTC" | "Etc/UTC" | "GMT"))),
+    };
+    let time = parse_stamp(time).ok_or_else(unreadable)?;
+    let due_at = match (utc, tzid) {
+        (true, _) => Utc.from_utc_datetime(&time).with_timezone(&Local),
+        (false, Some(tzid)) => {
+            let rules = zones.get(tzid).ok_or_else(|| format!("unknown time zone \"{}\"", tzid))?;
+            Utc.from_utc_datetime(&(time - Duration::seconds(zone_offset(rules, time) as i64))).with_timezone(&Local)
+        }
+        (false, None) => Local.from_local_datetime(&time).earliest().ok_or_else(unreadable)?,
+    };
+    Ok((due_at.date_naive(), Some(due_at)))
+}
+
+// Our own UIDs carry the task id, others are hashed into one so the same entry keeps its task
+fn task_id(uid: &str) -> Uuid {
+    uid.strip_suffix(UID_DOMAIN)
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .unwrap_or_else(|| Uuid::new_v5(&Uuid::NAMESPACE_OID, uid.as_bytes()))
+}
+
+pub fn import(content: &str) -> Result<Vec<TaskList>, Error> {
+    // unfold continuation lines first
+    let mut lines: Vec<String> = vec![];
+    for line in content.lines() {
+        match (line.strip_prefix(' ').or(line.strip_prefix('\t')), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let zones = time_zones(&lines);
+
+    let mut lists: Vec<TaskList> = vec![];
+    let mut entry: Option<(Task, String)> = None;
+    // depth of components nested in the current entry, such as alarms
+    let mut nested = 0;
+    for (number, line) in lines.iter().enumerate() {
+        let Some(property) = parse_property(line) else {
+            continue;
+        };
+        let value = property.value.to_uppercase();
+        match (property.name.as_str(), &mut entry) {
+            ("BEGIN", None) if value == "VTODO" || value == "VEVENT" => {
+                entry = Some((Task::new(String::new()), DEFAULT_LIST.to_string()));
+                nested = 0;
+            }
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) => {
+                let (task, list) = entry.take().unwrap();
+                if task.title.is_empty() {
+                    continue;
+                }
+                let index = list_index(&mut lists, &list);
+                lists[index].tasks.push(task);
+            }
+            (_, Some(_)) if nested > 0 => {}
+            (name, Some((task, list))) => match name {
+                "UID" => task.id = task_id(property.value.trim()),
+                "SUMMARY" => task.title = unescape(&property.value).trim().to_string(),
+                "DESCRIPTION" => task.notes = unescape(&property.value),
+                "DUE" | "DTSTART" => {
+                    // a to-do's start is not its deadline
+                    if name == "DTSTART" && !task.date_string.is_empty() {
+                        continue;
+                    }
+                    let (due, due_at) = parse_time(&property, &zones).map_err(|err| Error::ImportError(format!("line {}: {}", number + 1, err)))?;
+                    set_due(task, due, due_at);
+                }
+                "STATUS" => set_done(task, value == "COMPLETED", task.completed_at),
+                "COMPLETED" | EVENT_COMPLETED_PROPERTY => {
+                    let completed_at = parse_time(&property, &zones).ok().and_then(|(_, completed_at)| completed_at);
+                    set_done(task, true, completed_at);
+                }
+                "PRIORITY" => task.priority = task_priority(property.value.trim().parse().unwrap_or(0)),
+                "CATEGORIES" => task.tags.extend(split_list(&property.value).into_iter().map(|tag| tag.to_lowercase())),
+                LIST_PROPERTY => *list = unescape(&property.value),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    Ok(lists)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer::new_list;
+
+    const BERLIN: &str = "BEGIN:VTIMEZONE\r\nTZID:Europe/Berlin\r\nBEGIN:DAYLIGHT\r\nTZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200\r\nDTSTART:19700329T020000\r\nRRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r\nEND:DAYLIGHT\r\nBEGIN:STANDARD\r\nTZOFFSETFROM:+0200\r\nTZOFFSETTO:+0100\r\nDTSTART:19701025T030000\r\nRRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r\nEND:STANDARD\r\nEND:VTIMEZONE\r\n";
+
+    fn calendar(body: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}END:VCALENDAR\r\n", body)
+    }
+
+    fn todo(due: &str) -> String {
+        format!("BEGIN:VTODO\r\nUID:abc@example.com\r\nSUMMARY:Pay rent\r\n{}\r\nEND:VTODO\r\n", due)
+    }
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&parse_stamp(text).unwrap())
+    }
+
+    #[test]
+    fn long_lines_fold_and_unfold() {
+        let mut out = String::new();
+        let line = format!("SUMMARY:{}", "ä".repeat(60));
+        push_line(&mut out, &line);
+        let folded: Vec<&str> = out.split("\r\n").filter(|part| !part.is_empty()).collect();
+        assert!(folded.len() > 1);
+        assert!(folded.iter().all(|part| part.len() <= 75));
+        assert!(folded[1..].iter().all(|part| part.starts_with(' ')));
+        assert_eq!(folded.concat().replace("\r\n ", "").replacen(' ', "", folded.len() - 1), line);
+
+        let mut list = new_list("Errands");
+        let mut task = Task::new(format!("Buy {} and, well; more", "grüne Äpfel ".repeat(8).trim()));
+        set_due(&mut task, NaiveDate::from_ymd_opt(2024, 5, 3).unwrap(), None);
+        task.notes = "first line\nsecond line".to_string();
+        list.tasks.push(task.clone());
+        let lists = import(&export(&[list], false)).unwrap();
+        let imported = &lists[0].tasks[0];
+        assert_eq!(lists[0].title, "Errands");
+        assert_eq!((&imported.title, &imported.notes, imported.due), (&task.title, &task.notes, task.due));
+    }
+
+    #[test]
+    fn uids_round_trip_to_the_same_task() {
+        let id = Uuid::new_v4();
+        assert_eq!(task_id(&format!("{}{}", id, UID_DOMAIN)), id);
+        assert_eq!(task_id("abc@example.com"), task_id("abc@example.com"));
+        assert_ne!(task_id("abc@example.com"), task_id("abd@example.com"));
+
+        let mut list = new_list("Work");
+        let mut task = Task::new("Report".to_string());
+        set_due(&mut task, NaiveDate::from_ymd_opt(2024, 5, 3).unwrap(), None);
+        list.tasks.push(task.clone());
+        for events in [false, true] {
+            assert_eq!(import(&export(std::slice::from_ref(&list), events)).unwrap()[0].tasks[0].id, task.id);
+        }
+    }
+
+    #[test]
+    fn times_in_a_described_zone_are_honoured() {
+        let summer = import(&calendar(&format!("{}{}", BERLIN, todo("DUE;TZID=Europe/Berlin:20240703T120000")))).unwrap();
+        assert_eq!(summer[0].tasks[0].due_at.map(|due_at| due_at.with_timezone(&Utc)), Some(utc("20240703T100000")));
+        // the zone may come after the entries using it
+        let winter = import(&calendar(&format!("{}{}", todo("DUE;TZID=Europe/Berlin:20240103T120000"), BERLIN))).unwrap();
+        assert_eq!(winter[0].tasks[0].due_at.map(|due_at| due_at.with_timezone(&Utc)), Some(utc("20240103T110000")));
+        let named_utc = import(&calendar(&todo("DUE;TZID=UTC:20240103T120000"))).unwrap();
+        assert_eq!(named_utc[0].tasks[0].due_at.map(|due_at| due_at.with_timezone(&Utc)), Some(utc("20240103T120000")));
+    }
+
+    #[test]
+    fn times_in_an_unknown_zone_are_refused() {
+        let Err(err) = import(&calendar(&todo("DUE;TZID=America/New_York:20240103T120000"))) else {
+            panic!("a time in an unknown zone was read");
+        };
+        assert!(err.to_string().contains("unknown time zone \"America/New_York\""));
+        let unsupported = BERLIN.replace("FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU", "FREQ=MONTHLY;BYDAY=1SU");
+        assert!(import(&calendar(&format!("{}{}", unsupported, todo("DUE;TZID=Europe/Berlin:20240703T120000")))).is_err());
+    }
+
+    #[test]
+    fn weekdays_of_a_month() {
+        let date = |year, month, day| NaiveDate::from_ymd_opt(year, month, day);
+        assert_eq!(weekday_of_month(2024, 3, -1, Weekday::Sun), date(2024, 3, 31));
+        assert_eq!(weekday_of_month(2024, 10, -1, Weekday::Sun), date(2024, 10, 27));
+        assert_eq!(weekday_of_month(2024, 3, 2, Weekday::Sun), date(2024, 3, 10));
+        assert_eq!(weekday_of_month(2024, 2, -5, Weekday::Mon), None);
+        assert_eq!(parse_offset("-0530"), Some(-(5 * 3600 + 30 * 60)));
+        assert_eq!(parse_offset("0100"), None);
+    }
+}
@@ -4,18 +4,58 @@ use crossterm::{ event::{self, Event, KeyCode},
     ExecutableCommand,
 };
 use ratatui::{prelude::*, widgets::*};
-use home::*;
 
 /*** Taskboard specific includes ***/
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::BTreeSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use uuid::Uuid;
+
+mod activity;
+mod cli;
+mod db;
+mod history;
+mod ical;
+mod markdown;
+mod spreadsheet;
+mod sqlite;
+mod storage;
+mod sync;
+mod taskwarrior;
+#[cfg(test)]
+mod testing;
+mod todotxt;
+mod transfer;
+use cli::{parse_args, Command, USAGE};
+use db::{
+    board_path, check_board_name, delete_board, list_boards, recover_db, rename_board, resolve_db_path,
+    restore_command, DbFile, MAIN_BOARD,
+};
+use storage::{migrate_storage_command, open_storage, Backend};
+use sync::OpenBoard;
+use todotxt::sync_links;
+use transfer::{change_board, export_command, import_command, ImportOptions};
+use history::{redo, undo, History};
+use activity::timeline;
 
 const DEBUG: bool = true;
+// Autosave once the board has been left alone this long...
+const AUTOSAVE_DELAY: Duration = Duration::from_secs(1);
+// ...or at the latest this long after the first unsaved change
+const AUTOSAVE_MAX_DELAY: Duration = Duration::from_secs(10);
+// How often to look for changes other instances saved to the board file
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
 const COLOR1: Color = Color::White;
 const COLOR2: Color = Color::Rgb(0xff, 0xff, 0xff);
 const COLOR3: Color = Color::Yellow;
+const BLOCKED_COLOR: Color = Color::LightMagenta;
+const TAG_COLORS: [Color; 6] = [Color::Cyan, Color::Magenta, Color::Green, Color::Blue, Color::LightRed, Color::LightYellow];
 /*** Error handling for db reading ***/
 #[derive(Error, Debug)]
 pub enum Error {
@@ -23,6 +63,39 @@ pub enum Error {
     ReadDBError(#[from] io::Error),
     #[error("error parsing the DB file: {0}")]
     ParseDBError(#[from] serde_json::Error),
+    #[error("DB file format version {0} is newer than the supported version {1}")]
+    UnsupportedVersion(u64, u64),
+    #[error("invalid board name \"{0}\", use letters, digits, '-' and '_'")]
+    InvalidBoardName(String),
+    #[error("board \"{0}\" already exists")]
+    BoardExists(String),
+    #[error("board \"{0}\" does not exist")]
+    NoSuchBoard(String),
+    #[error("the main board cannot be renamed or deleted")]
+    MainBoard,
+    #[error("{0}, and the file could not be moved aside: {1}")]
+    QuarantineFailed(serde_json::Error, Box<Error>),
+    #[error("error in the SQLite DB: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+    #[error("{0} already exists")]
+    TargetExists(std::path::PathBuf),
+    #[error("the migrated copy of {0} did not read back identically, nothing was changed")]
+    MigrationMismatch(std::path::PathBuf),
+    #[error("no list titled \"{0}\"")]
+    NoSuchList(String),
+    #[error("cannot import: {0}")]
+    ImportError(String),
+    #[error("error in the CSV file: {0}")]
+    CsvError(#[from] csv::Error),
+}
+
+/*** Errors when linking tasks ***/
+#[derive(Error, Debug)]
+enum DependencyError {
+    #[error("a task cannot block itself")]
+    SelfBlock,
+    #[error("dependency cycle: {0}")]
+    Cycle(String),
 }
 
 /* The Taskboard struct represents all of the information needed to render the application
@@ -35,37 +108,303 @@ struct TaskBoard {
     lists: Vec<TaskList>,
     active_list: usize,
     debug_str: String,
+    checklist_selected: usize,
+    input: String,
+    tag_filter: TagFilter,
+    linking: Option<Uuid>,
+    board_name: String,
+    boards: Vec<String>,
+    board_selected: usize,
+    renaming_board: Option<String>,
+    #[serde(skip)]
+    board_action: Option<BoardAction>,
+    history: History,
+    activity: Vec<activity::Event>,
+    timeline: Option<Vec<String>>,
+    timeline_list: bool,
+    timeline_scroll: u16,
+}
+
+/*
+* Board picker requests, carried out by the main loop since they touch the disk
+* Open(String) - Save the current board and switch to the named one
+* Create(String) - Save the current board and switch to a new empty one
+* Rename(String, String) - Rename a board, following it if it is the open one
+* Delete(String) - Delete a board, falling back to the main board if it is the open one
+*/
+#[derive(Clone)]
+enum BoardAction {
+    Open(String),
+    Create(String),
+    Rename(String, String),
+    Delete(String),
+}
+
+/*
+* Board wide tag filter
+* tags: BTreeSet<String> - Tasks must carry every one of these tags to match, empty disables the filter
+* hide: bool - Hide non-matching tasks instead of dimming them
+*/
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+struct TagFilter {
+    tags: BTreeSet<String>,
+    hide: bool,
+}
+
+impl TagFilter {
+    fn matches(&self, task: &Task) -> bool {
+        self.tags.is_subset(&task.tags)
+    }
 }
 
 /*
 * The TaskList is a list of all current tasks
-* id: usize - A numeric id for the list
+* id: Uuid - Stable unique id for the list, never reused or renumbered
 * title: String - Name of the list, e.g., ECE 339
 * tasks: Vec<Task> - A vector of all Task structs contained in this TaskList
+* hide_completed: bool - Whether finished tasks are left out of the rendered list
+* created_at, updated_at: DateTime<Local> - When the list was created and last renamed or had tasks added/removed
 */
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 struct TaskList {
-    id: usize,
+    id: Uuid,
     title: String,
     tasks: Vec<Task>,
     selected: usize,
+    #[serde(default)]
+    hide_completed: bool,
+    #[serde(default = "Local::now")]
+    created_at: DateTime<Local>,
+    #[serde(default = "Local::now")]
+    updated_at: DateTime<Local>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+/*
+* A single item in a TaskList
+* id: Uuid - Stable unique id for the task, kept when it moves or is edited
+* created_at, updated_at: DateTime<Local> - When the task was created and last edited
+* done: bool - Set once the task is finished, finished tasks are kept instead of removed
+* completed_at: Option<DateTime<Local>> - When the task was last marked done
+* notes: String - Free-form, possibly multi-line, body shown in the detail pane
+* checklist: Vec<ChecklistItem> - Ordered steps needed to finish the task
+* priority: Priority - How much the task matters, pulls it ahead of earlier due tasks
+* tags: BTreeSet<String> - Cross-list labels, stored without the leading '#'
+* recurrence: Option<Recurrence> - Schedule for the next instance, created when this one is finished
+* due_at: Option<DateTime<Local>> - Exact deadline when a time of day was given, due holds its local date
+* blocked_by: Vec<Uuid> - Ids of tasks, in any list, that have to be finished before this one
+* parent: Option<Uuid> - The task in the same list this one is a subtask of
+* collapsed: bool - Whether this task's subtasks are hidden
+*/
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 struct Task {
+    #[serde(default = "Uuid::new_v4")]
+    id: Uuid,
+    #[serde(default = "Local::now")]
+    created_at: DateTime<Local>,
+    #[serde(default = "Local::now")]
+    updated_at: DateTime<Local>,
     title: String,
     date_string: String,
     due: NaiveDate,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    completed_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    notes: String,
+    #[serde(default)]
+    checklist: Vec<ChecklistItem>,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default)]
+    tags: BTreeSet<String>,
+    #[serde(default)]
+    recurrence: Option<Recurrence>,
+    #[serde(default)]
+    due_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    blocked_by: Vec<Uuid>,
+    #[serde(default)]
+    parent: Option<Uuid>,
+    #[serde(default)]
+    collapsed: bool,
+}
+
+impl Task {
+    fn new(title: String) -> Task {
+        let now = Local::now();
+        Task {
+            id: Uuid::new_v4(),
+            created_at: now,
+            updated_at: now,
+            title,
+            date_string: String::new(),
+            due: NaiveDate::from_ymd_opt(2102, 12, 1).unwrap(),
+            done: false,
+            completed_at: None,
+            notes: String::new(),
+            checklist: vec![],
+            priority: Priority::None,
+            tags: BTreeSet::new(),
+            recurrence: None,
+            due_at: None,
+            blocked_by: vec![],
+            parent: None,
+            collapsed: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    fn raise(self) -> Priority {
+        match self {
+            Priority::None => Priority::Low,
+            Priority::Low => Priority::Medium,
+            Priority::Medium => Priority::High,
+            Priority::High | Priority::Urgent => Priority::Urgent,
+        }
+    }
+
+    fn lower(self) -> Priority {
+        match self {
+            Priority::None | Priority::Low => Priority::None,
+            Priority::Medium => Priority::Low,
+            Priority::High => Priority::Medium,
+            Priority::Urgent => Priority::High,
+        }
+    }
+
+    // How many days earlier than its due date a task of this priority is sorted
+    fn lead_days(self) -> i64 {
+        match self {
+            Priority::None => 0,
+            Priority::Low => 1,
+            Priority::Medium => 3,
+            Priority::High => 7,
+            Priority::Urgent => 0,
+        }
+    }
+
+    fn marker(self) -> Span<'static> {
+        match self {
+            Priority::None => Span::raw(""),
+            Priority::Low => Span::styled("! ", Style::default().fg(Color::Blue)),
+            Priority::Medium => Span::styled("!! ", Style::default().fg(Color::Green)),
+            Priority::High => Span::styled("!!! ", Style::default().fg(Color::LightRed)),
+            Priority::Urgent => Span::styled("!!!! ", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct ChecklistItem {
+    text: String,
+    done: bool,
+}
+
+/*
+* How a recurring task repeats
+* rule: Repeat - Which dates the task falls on
+* end: RecurrenceEnd - When to stop creating new instances
+*/
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Recurrence {
+    rule: Repeat,
+    #[serde(default)]
+    end: RecurrenceEnd,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+enum Repeat {
+    Daily,
+    Weekdays,
+    Weekly(Vec<Weekday>),
+    // Day of the month, clamped to the last day in shorter months
+    Monthly(u32),
+    EveryNDays(u32),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+enum RecurrenceEnd {
+    #[default]
+    Never,
+    // Number of instances still to be created after the current one
+    After(u32),
+    Until(NaiveDate),
 }
 
 impl From<Task> for Text<'static> {
     fn from(task: Task) -> Self {
-        if task.date_string != String::new(){
-            Text::raw(format!("{} - {}", task.title, task.date_string))
+        let date_string = match task.completed_at {
+            Some(completed_at) if task.done => format!("done {}/{}", completed_at.month(), completed_at.day()),
+            _ => task.date_string,
+        };
+        let title = match task.checklist.len() {
+            0 => task.title,
+            total => {
+                let checked = task.checklist.iter().filter(|item| item.done).count();
+                format!("{} ({}/{})", task.title, checked, total)
+            }
+        };
+        let mut spans = vec![];
+        if !task.done {
+            spans.push(task.priority.marker());
+        }
+        spans.push(Span::raw(title));
+        if task.recurrence.is_some() {
+            spans.push(Span::raw(" \u{21bb}"));
+        }
+        for tag in task.tags.iter() {
+            spans.push(Span::raw(" "));
+            spans.push(tag_span(tag));
+        }
+        if date_string != String::new(){
+            spans.push(Span::raw(format!(" - {}", date_string)));
+        }
+        let text = Text::from(Line::from(spans));
+        if task.done {
+            dim_text(text).patch_style(Style::default().add_modifier(Modifier::CROSSED_OUT))
         } else {
-            Text::raw(format!("{}", task.title))
+            text
+        }
+    }
+}
+
+fn tag_span(tag: &str) -> Span<'static> {
+    let hash = tag.bytes().fold(0usize, |acc, b| acc.wrapping_mul(31).wrapping_add(b as usize));
+    Span::styled(format!("#{}", tag), Style::default().fg(TAG_COLORS[hash % TAG_COLORS.len()]))
+}
+
+// Grey out every span, overriding any colors set on them
+fn dim_text(text: Text<'static>) -> Text<'static> {
+    color_text(text, Color::DarkGray)
+}
+
+fn color_text(mut text: Text<'static>, color: Color) -> Text<'static> {
+    for line in text.lines.iter_mut() {
+        for span in line.spans.iter_mut() {
+            span.style = span.style.fg(color);
         }
     }
+    text
+}
+
+// Split user input such as "#bug, waiting" into a set of tags
+fn parse_tags(input: &str) -> BTreeSet<String> {
+    input.split(|c: char| c.is_whitespace() || c == ',')
+        .map(|tag| tag.trim_start_matches('#').to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect()
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -74,6 +413,17 @@ enum MenuItem {
     AddingList,
     AddingTaskTitle,
     AddingTaskDate,
+    EditingNotes,
+    Checklist,
+    AddingChecklistItem,
+    EditingTags,
+    FilteringTags,
+    EditingRecurrence,
+    PickingBlocker,
+    PickingBoard,
+    NamingBoard,
+    ViewingHistory,
+    DeletingBoard,
 }
 
 impl From<MenuItem> for usize {
@@ -83,38 +433,354 @@ impl From<MenuItem> for usize {
             MenuItem::AddingList => 1,
             MenuItem::AddingTaskTitle => 2,
             MenuItem::AddingTaskDate => 3,
+            MenuItem::EditingNotes => 4,
+            MenuItem::Checklist => 5,
+            MenuItem::AddingChecklistItem => 6,
+            MenuItem::EditingTags => 7,
+            MenuItem::FilteringTags => 8,
+            MenuItem::EditingRecurrence => 9,
+            MenuItem::PickingBlocker => 10,
+            MenuItem::PickingBoard => 11,
+            MenuItem::NamingBoard => 12,
+            MenuItem::ViewingHistory => 13,
+            MenuItem::DeletingBoard => 14,
         }
     }
 }
 
 fn main() -> io::Result<()> {
+    /*** command line ***/
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let (db_path, moved_note) = match resolve_db_path(args.db) {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            eprintln!("Failed to locate the DB file: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let board_name = args.board.unwrap_or_else(|| MAIN_BOARD.to_string());
+    if let Err(err) = check_board_name(&board_name) {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    }
+
+    /*** subcommands ***/
+    let command_result = match args.command {
+        Command::Run => None,
+        Command::Help => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Command::Restore(choice) => Some(restore_command(&board_path(&db_path, &board_name), choice.as_deref())),
+        Command::MigrateStorage(backend) => Some(migrate_storage_command(&db_path, &board_name, backend)),
+        Command::Export(format, file) => Some(export_command(&db_path, &board_name, format, args.list.as_deref(), file.as_deref())),
+        Command::Import(format, file) => {
+            let options = ImportOptions { columns: args.columns, date_format: args.date_format };
+            Some(import_command(&db_path, &board_name, format, args.list.as_deref(), &file, &options))
+        }
+        Command::TodoTxtSync(file) => {
+            let list = args.list.unwrap_or_default();
+            let path = board_path(&db_path, &board_name);
+            Some(change_board(&db_path, &board_name, |taskboard| todotxt::link_list(&path, taskboard, &list, file.as_deref())).map(|note| println!("{}", note)))
+        }
+    };
+    if let Some(result) = command_result {
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    /*** load the DB before touching the terminal so errors stay readable ***/
+    let (mut board, db, recovered_note) = match load_board(&board_path(&db_path, &board_name)) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let status = recovered_note.or(board.shared_note()).or(moved_note).unwrap_or_default();
+
     /*** set up terminal ***/
     enable_raw_mode()?;
     stdout().execute(EnterAlternateScreen)?;
     let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
 
     /*** initialize taskboard and home ***/
+    let history = board.history();
+    let mut taskboard = new_taskboard(db, board_name, status, history);
+
+    /*** flush the board on termination signals and panics ***/
+    let terminate = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGHUP] {
+        signal_hook::flag::register(signal, Arc::clone(&terminate))?;
+    }
+    let default_panic_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // leave the alternate screen first so the panic message is visible
+        let _ = disable_raw_mode();
+        let _ = stdout().execute(LeaveAlternateScreen);
+        default_panic_hook(info);
+    }));
+
     let mut active_menu_item = MenuItem::Home;
-    let mut quit = false;
-    let mut active_list_state = ListState::default();
-    active_list_state.select(Some(0));
-    let mut taskboard = TaskBoard{
-        num_lists: read_db().expect("valid read").len(), 
-        lists:read_db().expect("valid read"),
-        active_list: 1,
-        debug_str: String::from(""),
-    }; // Make a function that initialized the creation of the taskboard
-    
-    /*** main loop ***/
-    while !quit {
-        let _ = ui(&mut terminal, &mut taskboard, &mut active_menu_item);
-        quit = handle_events(&mut active_menu_item, &mut taskboard)?; 
-        update_dates(&mut taskboard);
-    }
-
-    let _ = write_db(&mut taskboard);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| run(&mut terminal, &mut taskboard, &mut active_menu_item, &db_path, &mut board, &terminate)));
+
+    // a task or list still being typed is dropped rather than flushed with its cursor and half typed text
+    let clean_exit = result.is_ok() && matches!(active_menu_item, MenuItem::Home);
+    discard_edit(&mut taskboard, active_menu_item);
+
+    // hand the last edits to synced todo.txt files before the final save, unless the session ended
+    // mid-edit or in a panic; the next start syncs them instead
+    let synced = match clean_exit {
+        true => sync_links(&board_path(&db_path, &taskboard.board_name), &mut taskboard),
+        false => Ok(None),
+    };
+    if let Ok(Some(_)) = synced {
+        let events = taskboard.history.record(&taskboard.lists);
+        taskboard.activity.extend(events);
+    }
+    let saved = board.save(&mut taskboard);
     disable_raw_mode()?;
     stdout().execute(LeaveAlternateScreen)?;
+    if let Err(err) = synced {
+        eprintln!("Failed to sync todo.txt: {}", err);
+    }
+    if let Err(err) = saved {
+        eprintln!("Failed to save the board: {}", err);
+    }
+    match result {
+        Ok(result) => result,
+        Err(panic) => panic::resume_unwind(panic),
+    }
+}
+
+/*** main loop ***/
+fn run(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    taskboard: &mut TaskBoard,
+    active_menu_item: &mut MenuItem,
+    db_path: &Path,
+    board: &mut OpenBoard,
+    terminate: &AtomicBool,
+) -> io::Result<()> {
+    let mut quit = false;
+    let mut last_change = Instant::now();
+    let mut unsaved_since: Option<Instant> = None;
+    let mut last_sync = Instant::now();
+    while !quit && !terminate.load(Ordering::Relaxed) {
+        let _ = ui(terminal, taskboard, active_menu_item);
+        // only key presses edit the board here, so the lists are compared around them alone
+        if event::poll(Duration::from_millis(50))? {
+            let before = taskboard.lists.clone();
+            let (active_list, tag_filter) = (taskboard.active_list, taskboard.tag_filter.clone());
+            quit = handle_events(active_menu_item, taskboard, db_path)?;
+            if !taskboard.history.take_applied() {
+                stamp_changes(&before, &mut taskboard.lists);
+            }
+            if taskboard.lists != before || taskboard.active_list != active_list || taskboard.tag_filter != tag_filter {
+                last_change = Instant::now();
+                unsaved_since.get_or_insert(last_change);
+            }
+        }
+        // a step covers everything done between two visits to Home, e.g. a whole new task
+        if let MenuItem::Home = active_menu_item {
+            let events = taskboard.history.record(&taskboard.lists);
+            taskboard.activity.extend(events);
+        }
+        update_dates(taskboard);
+
+        // the history view is filled from the log on disk plus what was not saved yet
+        if let (MenuItem::ViewingHistory, None) = (*active_menu_item, &taskboard.timeline) {
+            let mut events = board.events();
+            events.extend(taskboard.activity.iter().cloned());
+            let list = taskboard.active_list.checked_sub(1).and_then(|i| taskboard.lists.get(i)).map(|list| list.id).unwrap_or_default();
+            let task = selected_task(taskboard).map(|task| task.id).filter(|_| !taskboard.timeline_list);
+            taskboard.timeline = Some(timeline(&events, list, task));
+        }
+
+        if let Some(action) = taskboard.board_action.take() {
+            match apply_board_action(db_path, taskboard, board, action) {
+                // the open board was saved before acting on it
+                Ok(()) => unsaved_since = None,
+                Err(err) => taskboard.debug_str = format!("Board not changed: {}", err),
+            }
+        }
+
+        // only autosave from Home so half typed input is never written
+        if let (Some(since), MenuItem::Home) = (unsaved_since, *active_menu_item) {
+            if last_change.elapsed() >= AUTOSAVE_DELAY || since.elapsed() >= AUTOSAVE_MAX_DELAY {
+                unsaved_since = None;
+                match board.save(taskboard) {
+                    Ok(note) => {
+                        if let Some(note) = note {
+                            taskboard.debug_str = note;
+                        }
+                    }
+                    Err(err) => {
+                        taskboard.debug_str = format!("Autosave failed: {}", err);
+                        // try again after another delay
+                        last_change = Instant::now();
+                        unsaved_since = Some(last_change);
+                    }
+                }
+            }
+        }
+
+        // pick up what other instances saved, again only from Home
+        if let MenuItem::Home = active_menu_item {
+            if last_sync.elapsed() >= SYNC_INTERVAL {
+                last_sync = Instant::now();
+                match board.sync(taskboard) {
+                    Ok(Some(note)) => taskboard.debug_str = note,
+                    Ok(None) => {}
+                    Err(err) => taskboard.debug_str = format!("Could not reload the board: {}", err),
+                }
+                match sync_links(&board_path(db_path, &taskboard.board_name), taskboard) {
+                    Ok(Some(note)) => {
+                        taskboard.debug_str = note;
+                        last_change = Instant::now();
+                        unsaved_since.get_or_insert(last_change);
+                    }
+                    Ok(None) => {}
+                    Err(err) => taskboard.debug_str = format!("Could not sync todo.txt: {}", err),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Leaves `mode` the way Esc does, so only the task, list or checklist item still being typed and the
+// open input are dropped. Everything finished before, in any mode, stays
+fn discard_edit(taskboard: &mut TaskBoard, mode: MenuItem) {
+    match mode {
+        MenuItem::AddingTaskTitle | MenuItem::AddingTaskDate => {
+            if let Some(active_list) = taskboard.active_list.checked_sub(1).and_then(|i| taskboard.lists.get_mut(i)) {
+                if active_list.selected < active_list.tasks.len() {
+                    active_list.tasks.remove(active_list.selected);
+                }
+                fix_selection(active_list, &taskboard.tag_filter);
+            }
+        }
+        MenuItem::AddingList => {
+            taskboard.lists.pop();
+            taskboard.num_lists = taskboard.lists.len();
+            taskboard.active_list = taskboard.active_list.min(taskboard.num_lists);
+        }
+        MenuItem::AddingChecklistItem => {
+            let selected = taskboard.checklist_selected;
+            if let Some(task) = selected_task_mut(taskboard).filter(|task| selected < task.checklist.len()) {
+                task.checklist.remove(selected);
+            }
+        }
+        // notes keep what was typed, only the cursor goes
+        MenuItem::EditingNotes => {
+            if let Some(task) = selected_task_mut(taskboard) {
+                task.notes.pop();
+            }
+        }
+        _ => {}
+    }
+    taskboard.input.clear();
+}
+
+/*** Loading and switching boards ***/
+// Opens and reads a board file, salvaging what it can when a JSON file is damaged. Returns a
+// note for the status area when something had to be recovered
+type LoadedBoard = (OpenBoard, DbFile, Option<String>);
+
+fn load_board(path: &Path) -> Result<LoadedBoard, Error> {
+    let mut storage = open_storage(path)?;
+    match storage.load() {
+        Ok(db) => Ok((OpenBoard::new(path, storage, &db), db, None)),
+        Err(Error::ParseDBError(err)) if Backend::of(path) == Backend::Json => match recover_db(path, &err) {
+            Ok(recovery) => {
+                let note = format!(
+                    "DB file was damaged ({}), recovered {} lists and {} tasks. Original moved to {}",
+                    err, recovery.lists, recovery.tasks, recovery.quarantine.display()
+                );
+                Ok((OpenBoard::new(path, storage, &recovery.db), recovery.db, Some(note)))
+            }
+            Err(recover_err) => Err(Error::QuarantineFailed(err, Box::new(recover_err))),
+        },
+        Err(err) => Err(err),
+    }
+}
+
+fn new_taskboard(db: DbFile, board_name: String, status: String, mut history: History) -> TaskBoard {
+    history.resync(&db.lists);
+    TaskBoard {
+        num_lists: db.lists.len(),
+        active_list: db.board.active_list.clamp(1, db.lists.len().max(1)),
+        lists: db.lists,
+        debug_str: status,
+        checklist_selected: 0,
+        input: String::new(),
+        tag_filter: db.board.tag_filter,
+        linking: None,
+        board_name,
+        boards: vec![],
+        board_selected: 0,
+        renaming_board: None,
+        board_action: None,
+        history,
+        activity: vec![],
+        timeline: None,
+        timeline_list: false,
+        timeline_scroll: 0,
+    }
+}
+
+fn open_board(db_path: &Path, taskboard: &mut TaskBoard, board: &mut OpenBoard, name: String) -> Result<(), Error> {
+    let (new_board, db, note) = load_board(&board_path(db_path, &name))?;
+    let status = note.or(new_board.shared_note()).unwrap_or_else(|| format!("Opened board {}", name));
+    *taskboard = new_taskboard(db, name, status, new_board.history());
+    *board = new_board;
+    Ok(())
+}
+
+fn apply_board_action(db_path: &Path, taskboard: &mut TaskBoard, board: &mut OpenBoard, action: BoardAction) -> Result<(), Error> {
+    board.save(taskboard)?;
+    match action {
+        BoardAction::Open(name) => {
+            if name != taskboard.board_name {
+                open_board(db_path, taskboard, board, name)?;
+            }
+        }
+        BoardAction::Create(name) => {
+            check_board_name(&name)?;
+            if list_boards(db_path)?.contains(&name) {
+                return Err(Error::BoardExists(name));
+            }
+            open_board(db_path, taskboard, board, name)?;
+        }
+        BoardAction::Rename(from, to) => {
+            rename_board(db_path, &from, &to)?;
+            // reopen so the storage follows the file to its new name
+            if from == taskboard.board_name {
+                open_board(db_path, taskboard, board, to.clone())?;
+            }
+            taskboard.debug_str = format!("Renamed board {} to {}", from, to);
+        }
+        BoardAction::Delete(name) => {
+            if name == MAIN_BOARD {
+                return Err(Error::MainBoard);
+            }
+            if name == taskboard.board_name {
+                open_board(db_path, taskboard, board, MAIN_BOARD.to_string())?;
+            }
+            delete_board(db_path, &name)?;
+            taskboard.debug_str = format!("Deleted board {}, bring it back with --board {} restore", name, name);
+        }
+    }
     Ok(())
 }
 
@@ -123,7 +789,7 @@ fn ui(terminal: &mut Terminal<CrosstermBackend<Stdout>>, taskboard: &mut TaskBoa
     terminal.draw(|frame| {
         let size = frame.size();
         let mut constraints = vec![
-                Constraint::Length(3),
+                Constraint::Length(4),
                 Constraint::Min(2),
         ];
         if DEBUG {
@@ -136,14 +802,21 @@ fn ui(terminal: &mut Terminal<CrosstermBackend<Stdout>>, taskboard: &mut TaskBoa
             .split(size);
 
         /*** Help menu ***/
-        let help_info = get_helpline();
+        let help_info = match active_menu_item {
+            MenuItem::EditingNotes => get_notes_helpline(),
+            MenuItem::Checklist | MenuItem::AddingChecklistItem => get_checklist_helpline(),
+            MenuItem::PickingBoard | MenuItem::NamingBoard | MenuItem::DeletingBoard => get_boards_helpline(),
+            MenuItem::ViewingHistory => get_history_helpline(),
+            _ => get_helpline(),
+        };
         let help = Paragraph::new(help_info.clone()) .style(Style::default().fg(Color::Gray))
             .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .style(Style::default().fg(COLOR1))
-                    .title("Commands")
+                    .title(format!("Commands - board {}", taskboard.board_name))
                     .border_type(BorderType::Plain),
                 );
         frame.render_widget(help, chunks[0]);
@@ -168,12 +841,18 @@ fn ui(terminal: &mut Terminal<CrosstermBackend<Stdout>>, taskboard: &mut TaskBoa
                 for _i in 0..taskboard.num_lists{
                     constraints.push(Constraint::Min(0));
                 }
+                let panes = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([
+                        Constraint::Min(0),
+                        Constraint::Percentage(30),
+                    ])
+                    .split(chunks[1]);
                 let home = Layout::default()
                     .direction(Direction::Horizontal)
                     .constraints(constraints)
-                    .split(chunks[1]);
+                    .split(panes[0]);
 
-                let mut task_list_state = ListState::default().with_selected(Some(taskboard.lists[taskboard.active_list - 1].selected));
                 for (i, list) in taskboard.lists.clone().into_iter().enumerate(){
                     let taskboard_list = Layout::default()
                         .direction(Direction::Vertical)
@@ -184,7 +863,7 @@ fn ui(terminal: &mut Terminal<CrosstermBackend<Stdout>>, taskboard: &mut TaskBoa
                         .split(home[i]);
                     let color = match active_menu_item {
                         MenuItem::AddingList => {
-                            if list.id == taskboard.active_list{
+                            if i + 1 == taskboard.active_list{
                                 COLOR3
                             } else {
                                 COLOR1
@@ -205,7 +884,7 @@ fn ui(terminal: &mut Terminal<CrosstermBackend<Stdout>>, taskboard: &mut TaskBoa
 
                     let color = match active_menu_item {
                         MenuItem::Home => {
-                            if list.id == taskboard.active_list {
+                            if i + 1 == taskboard.active_list {
                                 COLOR3
                             } else {
                                 COLOR1
@@ -213,32 +892,127 @@ fn ui(terminal: &mut Terminal<CrosstermBackend<Stdout>>, taskboard: &mut TaskBoa
                         },
                         MenuItem::AddingList => COLOR1,
                         _ => 
-                            if list.id == taskboard.active_list {
+                            if i + 1 == taskboard.active_list {
                                 COLOR3
                             } else {
                                 COLOR1
                             }
                     };
-                    let empty = list.tasks.is_empty();
-                    let list_out = List::new(list.tasks)
-                            .block(Block::default().fg(color).title("List").borders(Borders::ALL))
+                    let visible = visible_tasks(&list, &taskboard.tag_filter);
+                    let empty = visible.is_empty();
+                    let mut task_list_state = ListState::default().with_selected(visible.iter().position(|&i| i == list.selected));
+                    let hidden = list.tasks.len() - visible.len();
+                    let block_title = match hidden {
+                        0 => "List".to_string(),
+                        _ => format!("List ({} hidden)", hidden),
+                    };
+                    let items = visible.iter().map(|&i| {
+                        let task = list.tasks[i].clone();
+                        let matches = taskboard.tag_filter.matches(&task);
+                        let blocked = is_blocked(taskboard, &task);
+                        let subtask_due = earliest_subtask_due(&list, task.id).filter(|&due| task.date_string.is_empty() || due < task.due);
+                        let fold_marker = match (has_subtasks(&list, task.id), task.collapsed) {
+                            (false, _) => "  ",
+                            (true, false) => "\u{25be} ",
+                            (true, true) => "\u{25b8} ",
+                        };
+                        let mut text = Text::from(task);
+                        if blocked {
+                            text = color_text(text, BLOCKED_COLOR);
+                            text.lines[0].spans.insert(0, Span::styled("\u{2298} ", Style::default().fg(BLOCKED_COLOR)));
+                        }
+                        if let Some(due) = subtask_due {
+                            text.lines[0].spans.push(Span::styled(format!(" (sub {})", short_date(due)), Style::default().fg(COLOR3)));
+                        }
+                        text.lines[0].spans.insert(0, Span::raw(format!("{}{}", "  ".repeat(task_depth(&list, i)), fold_marker)));
+                        if matches {
+                            text
+                        } else {
+                            dim_text(text)
+                        }
+                    });
+                    let list_out = List::new(items)
+                            .block(Block::default().fg(color).title(block_title).borders(Borders::ALL))
                             .style(Style::default().fg(COLOR2))
                             .highlight_style(Style::default().add_modifier(Modifier::ITALIC))
                             .highlight_symbol(">>")
                             .repeat_highlight_symbol(true)
                             .direction(ListDirection::TopToBottom);
-                    if list.id == taskboard.active_list && !empty{
+                    if i + 1 == taskboard.active_list && !empty{
                         frame.render_stateful_widget(list_out, taskboard_list[1], &mut task_list_state);
                     } else {
                         frame.render_widget(list_out, taskboard_list[1]);
                     }
                     frame.render_widget(title, taskboard_list[0]);
                 }
+                frame.render_widget(get_details(taskboard, active_menu_item), panes[1]);
             }
         }
 
+        /*** Board picker ***/
+        if let MenuItem::PickingBoard | MenuItem::NamingBoard | MenuItem::DeletingBoard = active_menu_item {
+            let height = (taskboard.boards.len() as u16 + 2).min(chunks[1].height);
+            let width = (chunks[1].width / 3).max(24).min(chunks[1].width);
+            let area = Rect::new(
+                chunks[1].x + (chunks[1].width - width) / 2,
+                chunks[1].y + (chunks[1].height - height) / 2,
+                width,
+                height,
+            );
+            let items = taskboard.boards.iter().map(|name| match *name == taskboard.board_name {
+                true => Line::from(vec![Span::raw(name.clone()), Span::styled(" (open)", Style::default().fg(COLOR3))]),
+                false => Line::from(name.clone()),
+            });
+            let picker = List::new(items)
+                .block(Block::default().fg(COLOR3).title("Boards").borders(Borders::ALL))
+                .style(Style::default().fg(COLOR2))
+                .highlight_style(Style::default().add_modifier(Modifier::ITALIC))
+                .highlight_symbol(">>");
+            let mut picker_state = ListState::default().with_selected(Some(taskboard.board_selected));
+            frame.render_widget(Clear, area);
+            frame.render_stateful_widget(picker, area, &mut picker_state);
+        }
+
+        /*** History view ***/
+        if let (MenuItem::ViewingHistory, Some(lines)) = (&active_menu_item, &taskboard.timeline) {
+            let title = match (selected_task(taskboard), taskboard.active_list.checked_sub(1).and_then(|i| taskboard.lists.get(i))) {
+                (Some(task), _) if !taskboard.timeline_list => format!("History of task \"{}\"", task.title),
+                (_, Some(list)) => format!("History of list \"{}\"", list.title),
+                _ => "History".to_string(),
+            };
+            let text = match lines.is_empty() {
+                true => Text::from("Nothing recorded yet"),
+                false => Text::from(lines.iter().map(|line| Line::from(line.clone())).collect::<Vec<_>>()),
+            };
+            let history = Paragraph::new(text)
+                .style(Style::default().fg(COLOR2))
+                .scroll((taskboard.timeline_scroll, 0))
+                .block(Block::default().fg(COLOR3).title(title).borders(Borders::ALL));
+            frame.render_widget(Clear, chunks[1]);
+            frame.render_widget(history, chunks[1]);
+        }
+
         /*** Debug ***/
-        let copyright = Paragraph::new(taskboard.debug_str.clone())
+        let status = match active_menu_item {
+            MenuItem::EditingTags => format!("Tags: {}", taskboard.input),
+            MenuItem::FilteringTags => format!("Filter by tags: {}", taskboard.input),
+            MenuItem::AddingTaskDate => "Due date: YYYY/MM/DD, YYYY/MM/DD HH:MM or HH:MM for today".to_string(),
+            MenuItem::PickingBlocker => {
+                let title = taskboard.linking.and_then(|id| find_task(taskboard, id)).map(|task| task.title.clone()).unwrap_or_default();
+                format!("Select the task that blocks \"{}\" and press Enter to link or unlink it (Esc to cancel)", title)
+            }
+            MenuItem::EditingRecurrence => format!("Repeat (daily, weekdays, weekly mon,thu, monthly 31, every 3 days; until YYYY/MM/DD or for N): {}", taskboard.input),
+            MenuItem::NamingBoard => match &taskboard.renaming_board {
+                Some(name) => format!("Rename board {} to: {}", name, taskboard.input),
+                None => format!("New board name: {}", taskboard.input),
+            },
+            MenuItem::DeletingBoard => {
+                let name = taskboard.boards.get(taskboard.board_selected).cloned().unwrap_or_default();
+                format!("Delete board {}? A backup of it is kept for restore (y/N)", name)
+            }
+            _ => taskboard.debug_str.clone(),
+        };
+        let copyright = Paragraph::new(status)
             .style(Style::default().fg(COLOR2))
             .alignment(Alignment::Center)
             .block(
@@ -255,64 +1029,133 @@ fn ui(terminal: &mut Terminal<CrosstermBackend<Stdout>>, taskboard: &mut TaskBoa
     Ok(0)
 }
 
-fn read_db() -> Result<Vec<TaskList>, Error> {
-    let db_path = match home_dir() {
-        Some(mut path) if !path.as_os_str().is_empty() => {
-            path.push(".data.json");
-            path
-        },
-        _ => std::process::exit(1), 
+/*** Detail pane for the selected task ***/
+fn get_details(taskboard: &TaskBoard, active_menu_item: &MenuItem) -> Paragraph<'static> {
+    let color = match active_menu_item {
+        MenuItem::EditingNotes | MenuItem::Checklist | MenuItem::AddingChecklistItem => COLOR3,
+        _ => COLOR1,
     };
-        
-    // let db_content = fs::read_to_string(db_path).expect("Failed to read JSON");
-    let db_content = match fs::read_to_string(db_path.as_path()) {
-        Ok(db_content) =>{db_content}
-        Err(error) if error.kind() == io::ErrorKind::NotFound => {
-            if let Err(e) = fs::create_dir_all(db_path.parent().unwrap()) {
-                eprintln!("Failed to create directories: {}", e);
-                std::process::exit(1);
-            }
-            fs::File::create(db_path).expect("Failed to create JSON file");
-            "".to_string()
-        }
-        Err(error) => {
-            // Some other error occurred
-            eprintln!("Failed to read JSON file: {}", error);
-            std::process::exit(1);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .style(Style::default().fg(color))
+        .title("Details")
+        .border_type(BorderType::Plain);
+    let task = match selected_task(taskboard) {
+        Some(task) => task,
+        None => {
+            return Paragraph::new("No task selected")
+                .style(Style::default().fg(COLOR2))
+                .alignment(Alignment::Center)
+                .block(block);
         }
     };
-    let parsed: Vec<TaskList> = match serde_json::from_str(&db_content){
-        Ok(parsed) => parsed,
-        Err(_err) => vec![],
-    };
-    Ok(parsed)
+
+    let mut lines = vec![
+        Line::styled(task.title.clone(), Style::default().add_modifier(Modifier::BOLD)),
+        Line::raw(match (task.date_string.as_str(), task.due_at) {
+            ("", _) => "Due: none".to_string(),
+            (date_string, Some(due_at)) => format!("Due: {} ({})", due_at.format("%Y/%m/%d %H:%M %:z"), date_string),
+            (date_string, None) => format!("Due: {} ({})", task.due.format("%Y/%m/%d"), date_string),
+        }),
+    ];
+    if task.priority != Priority::None {
+        lines.push(Line::from(vec![
+            Span::raw("Priority: "),
+            task.priority.marker(),
+            Span::raw(format!("{:?}", task.priority)),
+        ]));
+    }
+    if !task.tags.is_empty() {
+        let mut spans = vec![Span::raw("Tags:")];
+        for tag in task.tags.iter() {
+            spans.push(Span::raw(" "));
+            spans.push(tag_span(tag));
+        }
+        lines.push(Line::from(spans));
+    }
+    if let Some(recurrence) = &task.recurrence {
+        lines.push(Line::raw(format!("Repeats: {}", recurrence)));
+    }
+    let blockers: Vec<&Task> = task.blocked_by.iter().filter_map(|&id| find_task(taskboard, id)).collect();
+    if !blockers.is_empty() {
+        lines.push(Line::raw("Blocked by:"));
+        for blocker in blockers {
+            let line = format!("  {} ({})", blocker.title, if blocker.done { "done" } else { "open" });
+            if blocker.done {
+                lines.push(Line::styled(line, Style::default().fg(Color::DarkGray)));
+            } else {
+                lines.push(Line::styled(line, Style::default().fg(BLOCKED_COLOR)));
+            }
+        }
+    }
+    if let Some(due) = earliest_subtask_due(&taskboard.lists[taskboard.active_list - 1], task.id) {
+        lines.push(Line::raw(format!("Earliest subtask due: {}", due.format("%Y/%m/%d"))));
+    }
+    let blocks: Vec<String> = taskboard.lists.iter()
+        .flat_map(|list| list.tasks.iter())
+        .filter(|other| other.blocked_by.contains(&task.id))
+        .map(|other| other.title.clone())
+        .collect();
+    if !blocks.is_empty() {
+        lines.push(Line::raw(format!("Blocks: {}", blocks.join(", "))));
+    }
+    if let Some(completed_at) = task.completed_at.filter(|_| task.done) {
+        lines.push(Line::raw(format!("Done: {}", completed_at.format("%Y/%m/%d %H:%M"))));
+    }
+    lines.push(Line::styled(
+        format!("Created {} - Updated {}", task.created_at.format("%Y/%m/%d %H:%M"), task.updated_at.format("%Y/%m/%d %H:%M")),
+        Style::default().fg(Color::DarkGray),
+    ));
+    lines.push(Line::styled(format!("ID {}", task.id), Style::default().fg(Color::DarkGray)));
+    if !task.checklist.is_empty() {
+        let checked = task.checklist.iter().filter(|item| item.done).count();
+        lines.push(Line::raw(""));
+        lines.push(Line::styled(
+            format!("Checklist ({}/{})", checked, task.checklist.len()),
+            Style::default().add_modifier(Modifier::UNDERLINED),
+        ));
+        let in_checklist = matches!(active_menu_item, MenuItem::Checklist | MenuItem::AddingChecklistItem);
+        for (i, item) in task.checklist.iter().enumerate() {
+            let marker = if i == taskboard.checklist_selected && in_checklist { ">>" } else { "  " };
+            let text = format!("{}[{}] {}", marker, if item.done { "x" } else { " " }, item.text);
+            if item.done {
+                lines.push(Line::styled(text, Style::default().fg(Color::DarkGray)));
+            } else {
+                lines.push(Line::raw(text));
+            }
+        }
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::styled("Notes", Style::default().add_modifier(Modifier::UNDERLINED)));
+    if task.notes.is_empty() {
+        lines.push(Line::styled("Press e to add notes", Style::default().fg(Color::DarkGray)));
+    }
+    for note_line in task.notes.lines() {
+        lines.push(Line::raw(note_line.to_string()));
+    }
+
+    Paragraph::new(lines)
+        .style(Style::default().fg(COLOR2))
+        .wrap(Wrap { trim: false })
+        .block(block)
 }
 
 fn create_list(taskboard: &mut TaskBoard) {
+    let now = Local::now();
     let new_list = TaskList {
-        id:  taskboard.num_lists + 1,
+        id: Uuid::new_v4(),
         title: String::from("|"),
         tasks: vec![],
         selected: 0,
+        hide_completed: false,
+        created_at: now,
+        updated_at: now,
     };
 
     taskboard.lists.push(new_list);
     taskboard.num_lists += 1;
 }
 
-fn write_db(taskboard: &mut TaskBoard) -> Result<Vec<TaskList>, Error>{
-    let db_path = match home_dir() {
-        Some(mut path) if !path.as_os_str().is_empty() => {
-            path.push(".data.json");
-            path
-        },
-        _ => std::process::exit(1), 
-    };
-    let tasklists = taskboard.lists.clone();
-    fs::write(db_path, serde_json::to_vec(&tasklists)?)?;
-    Ok(tasklists)
-}
-
 fn delete_list(taskboard: &mut TaskBoard) {
     match taskboard.num_lists {
         0 => {},
@@ -323,10 +1166,39 @@ fn delete_list(taskboard: &mut TaskBoard) {
     }
 }
 
+// Bump updated_at on every list and task that differs from its state before the last key press
+fn stamp_changes(before: &[TaskList], after: &mut [TaskList]) {
+    let now = Local::now();
+    for list in after.iter_mut() {
+        let Some(old_list) = before.iter().find(|old_list| old_list.id == list.id) else {
+            continue;
+        };
+        let mut list_changed = old_list.title != list.title || old_list.tasks.len() != list.tasks.len();
+        for task in list.tasks.iter_mut() {
+            match old_list.tasks.iter().find(|old_task| old_task.id == task.id) {
+                Some(old_task) if old_task != task => task.updated_at = now,
+                Some(_) => {}
+                None => {
+                    task.updated_at = now;
+                    list_changed = true;
+                }
+            }
+        }
+        if list_changed {
+            list.updated_at = now;
+        }
+    }
+}
+
 fn update_dates(taskboard: &mut TaskBoard){
     // Update strings 
+    let now = Local::now();
     for list in taskboard.lists.iter_mut(){
         for task in list.tasks.iter_mut() {
+            if let Some(due_at) = task.due_at {
+                task.date_string = due_time_label(due_at, now);
+                continue;
+            }
             let due_diff = NaiveDateTime::new(task.due, NaiveTime::from_hms_opt(0, 0, 0).unwrap()) - NaiveDateTime::new(Local::now().naive_local().date(), NaiveTime::from_hms_opt(0, 0, 0).unwrap());
             match due_diff.num_days() {
                 0 => task.date_string = "Today".to_string(),
@@ -338,76 +1210,901 @@ fn update_dates(taskboard: &mut TaskBoard){
         }
     }
 
-    // Sort tasks by due date
+    // Sort tasks by priority and due date
     for list in taskboard.lists.iter_mut() {
-        list.tasks.sort_by(|a, b| a.due.cmp(&b.due));
+        sort_tasks(list);
     }
 }
-fn get_helpline() -> Line<'static>{
-    Line::from(vec![
-        Span::styled(
-            "<num>",
-            Style::default()
-                .fg(COLOR1)
-                .add_modifier(Modifier::UNDERLINED),
-        ),
-        Span::styled(
-            " Select List - ",
-            Style::default()
-                .fg(COLOR2)
-        ),
-        Span::styled(
-            "N",
-            Style::default()
-                .fg(COLOR1)
-                .add_modifier(Modifier::UNDERLINED),
-        ),
-        Span::styled(
-            "ew List - ",
-            Style::default()
-                .fg(COLOR2)
-        ),
-        Span::styled(
-            "D",
-            Style::default()
-                .fg(COLOR1)
-                .add_modifier(Modifier::UNDERLINED),
-        ),
-        Span::styled(
-            "elete List - ",
-            Style::default()
-                .fg(COLOR2)
-        ),
-        Span::styled(
-            "A",
-            Style::default()
-                .fg(COLOR1)
-                .add_modifier(Modifier::UNDERLINED),
-        ),
-        Span::styled(
-            "dd item - ",
-            Style::default()
-                .fg(COLOR2)
-        ),
-        Span::styled(
-            "d",
-            Style::default()
-                .fg(COLOR1)
-                .add_modifier(Modifier::UNDERLINED),
+
+// Urgent tasks come first, every other priority moves a task ahead by its lead time.
+// Parents sort by the earliest due date in their subtree and are followed by their subtasks.
+// The selection follows the task it was on.
+fn sort_tasks(list: &mut TaskList) {
+    let mut order: Vec<usize> = (0..list.tasks.len()).collect();
+    order.sort_by_cached_key(|&i| {
+        let task = &list.tasks[i];
+        let due = earliest_subtask_due(list, task.id).map_or(task.due, |due| due.min(task.due));
+        (
+            task.priority != Priority::Urgent,
+            due - chrono::Duration::days(task.priority.lead_days()),
+            // timed deadlines come before tasks that are due some time that day
+            task.due_at.map(|due_at| due_at.time()).unwrap_or(NaiveTime::from_hms_opt(23, 59, 59).unwrap()),
+            std::cmp::Reverse(task.priority),
+        )
+    });
+
+    fn push_subtree(list: &TaskList, order: &[usize], i: usize, tree_order: &mut Vec<usize>) {
+        tree_order.push(i);
+        for &child in order.iter().filter(|&&child| list.tasks[child].parent == Some(list.tasks[i].id)) {
+            push_subtree(list, order, child, tree_order);
+        }
+    }
+    let mut tree_order = vec![];
+    for &root in order.iter().filter(|&&i| parent_index(list, i).is_none()) {
+        push_subtree(list, &order, root, &mut tree_order);
+    }
+    // tasks caught in a parent loop from a hand edited file are kept at the bottom
+    for &i in order.iter() {
+        if !tree_order.contains(&i) {
+            tree_order.push(i);
+        }
+    }
+
+    list.selected = tree_order.iter().position(|&i| i == list.selected).unwrap_or(list.selected);
+    list.tasks = tree_order.into_iter().map(|i| list.tasks[i].clone()).collect();
+}
+
+/*** Subtasks ***/
+fn parent_index(list: &TaskList, i: usize) -> Option<usize> {
+    let parent = list.tasks[i].parent?;
+    list.tasks.iter().position(|task| task.id == parent)
+}
+
+fn task_depth(list: &TaskList, i: usize) -> usize {
+    let mut depth = 0;
+    let mut current = i;
+    while let Some(parent) = parent_index(list, current) {
+        depth += 1;
+        current = parent;
+        if depth >= list.tasks.len() {
+            break;
+        }
+    }
+    depth
+}
+
+fn has_subtasks(list: &TaskList, id: Uuid) -> bool {
+    list.tasks.iter().any(|task| task.parent == Some(id))
+}
+
+// Earliest due date among the unfinished subtasks, at any depth, that have one
+fn earliest_subtask_due(list: &TaskList, id: Uuid) -> Option<NaiveDate> {
+    let mut earliest: Option<NaiveDate> = None;
+    let mut stack = vec![id];
+    let mut seen = BTreeSet::new();
+    while let Some(current) = stack.pop() {
+        if !seen.insert(current) {
+            continue;
+        }
+        for child in list.tasks.iter().filter(|task| task.parent == Some(current)) {
+            if !child.done && !child.date_string.is_empty() {
+                earliest = Some(earliest.map_or(child.due, |due| due.min(child.due)));
+            }
+            stack.push(child.id);
+        }
+    }
+    earliest
+}
+
+fn hidden_by_collapse(list: &TaskList, i: usize) -> bool {
+    let mut current = i;
+    let mut steps = 0;
+    while let Some(parent) = parent_index(list, current) {
+        if list.tasks[parent].collapsed {
+            return true;
+        }
+        current = parent;
+        steps += 1;
+        if steps >= list.tasks.len() {
+            break;
+        }
+    }
+    false
+}
+
+// '>' makes the selected task a subtask of the sibling above it, '<' moves it up a level
+fn indent_selected(taskboard: &mut TaskBoard, indent: bool) {
+    if selected_task(taskboard).is_none() {
+        return;
+    }
+    let list = &mut taskboard.lists[taskboard.active_list - 1];
+    let selected = list.selected;
+    let parent = list.tasks[selected].parent;
+    if indent {
+        let sibling = list.tasks[..selected].iter_mut().rev()
+            .take_while(|task| Some(task.id) != parent)
+            .find(|task| task.parent == parent);
+        if let Some(sibling) = sibling {
+            sibling.collapsed = false;
+            let sibling_id = sibling.id;
+            list.tasks[selected].parent = Some(sibling_id);
+        }
+    } else if let Some(parent_index) = parent_index(list, selected) {
+        list.tasks[selected].parent = list.tasks[parent_index].parent;
+    }
+}
+
+/*** Due times ***/
+// Parses "YYYY/MM/DD", "YYYY/MM/DD HH:MM" or "HH:MM" (today) into a due date and optional deadline
+fn parse_due(input: &str) -> Option<(NaiveDate, Option<DateTime<Local>>)> {
+    let input = input.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y/%m/%d") {
+        return Some((date, None));
+    }
+    let naive = NaiveDateTime::parse_from_str(input, "%Y/%m/%d %H:%M").ok().or_else(|| {
+        NaiveTime::parse_from_str(input, "%H:%M").ok().map(|time| Local::now().date_naive().and_time(time))
+    })?;
+    // earliest() resolves times repeated by a DST change, times skipped by one are rejected
+    let due_at = Local.from_local_datetime(&naive).earliest()?;
+    Some((due_at.date_naive(), Some(due_at)))
+}
+
+// e.g. "in 45m", "in 2h", "due 17:00", "Tomorrow 09:30", "10/21 17:00"
+fn due_time_label(due_at: DateTime<Local>, now: DateTime<Local>) -> String {
+    let remaining = due_at - now;
+    let days = (due_at.date_naive() - now.date_naive()).num_days();
+    match remaining.num_minutes() {
+        ..=-1 => "Overdue".to_string(),
+        0..=59 => format!("in {}m", remaining.num_minutes()),
+        60..=359 => format!("in {}h", remaining.num_hours()),
+        _ if days == 0 => format!("due {}", due_at.format("%H:%M")),
+        _ if days == 1 => format!("Tomorrow {}", due_at.format("%H:%M")),
+        _ => format!("{} {}", short_date(due_at.date_naive()), due_at.format("%H:%M")),
+    }
+}
+
+/*** Recurring tasks ***/
+impl std::fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.rule {
+            Repeat::Daily => write!(f, "daily")?,
+            Repeat::Weekdays => write!(f, "weekdays")?,
+            Repeat::Weekly(days) => {
+                let days: Vec<String> = days.iter().map(|day| day.to_string().to_lowercase()).collect();
+                write!(f, "weekly {}", days.join(","))?
+            }
+            Repeat::Monthly(day) => write!(f, "monthly {}", day)?,
+            Repeat::EveryNDays(n) => write!(f, "every {} days", n)?,
+        }
+        match self.end {
+            RecurrenceEnd::Never => Ok(()),
+            RecurrenceEnd::After(n) => write!(f, " for {}", n),
+            RecurrenceEnd::Until(date) => write!(f, " until {}", date.format("%Y/%m/%d")),
+        }
+    }
+}
+
+impl std::str::FromStr for Recurrence {
+    type Err = String;
+
+    // Parses the same format Display produces, e.g. "weekly mon,thu until 2024/06/01"
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim().to_lowercase();
+        let (rule, end) = match input.split_once(" until ") {
+            Some((rule, date)) => match NaiveDate::parse_from_str(date.trim(), "%Y/%m/%d") {
+                Ok(date) => (rule, RecurrenceEnd::Until(date)),
+                Err(_) => return Err(format!("bad end date: {}", date.trim())),
+            },
+            None => match input.split_once(" for ") {
+                Some((rule, count)) => match count.trim().trim_end_matches(" times").parse() {
+                    Ok(count) => (rule, RecurrenceEnd::After(count)),
+                    Err(_) => return Err(format!("bad count: {}", count.trim())),
+                },
+                None => (input.as_str(), RecurrenceEnd::Never),
+            },
+        };
+
+        let words: Vec<&str> = rule.split(|c: char| c.is_whitespace() || c == ',').filter(|w| !w.is_empty()).collect();
+        let rule = match words.as_slice() {
+            ["daily"] => Repeat::Daily,
+            ["weekdays"] => Repeat::Weekdays,
+            ["weekly", days @ ..] if !days.is_empty() => {
+                let days: Result<Vec<Weekday>, _> = days.iter().map(|day| day.parse::<Weekday>()).collect();
+                match days {
+                    Ok(days) => Repeat::Weekly(days),
+                    Err(_) => return Err(format!("bad weekday in: {}", rule)),
+                }
+            }
+            ["monthly", day] => match day.parse() {
+                Ok(day @ 1..=31) => Repeat::Monthly(day),
+                _ => return Err(format!("bad day of month: {}", day)),
+            },
+            ["every", n] | ["every", n, "days"] => match n.parse() {
+                Ok(n) if n > 0 => Repeat::EveryNDays(n),
+                _ => return Err(format!("bad number of days: {}", n)),
+            },
+            _ => return Err(format!("unknown repeat rule: {}", rule)),
+        };
+        Ok(Recurrence { rule, end })
+    }
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|first| first.pred_opt())
+        .map(|last| last.day())
+        .unwrap_or(28)
+}
+
+// The first date after `from` that the rule falls on
+fn next_due_date(rule: &Repeat, from: NaiveDate) -> NaiveDate {
+    match rule {
+        Repeat::Daily => from + chrono::Duration::days(1),
+        Repeat::EveryNDays(n) => from + chrono::Duration::days(*n as i64),
+        Repeat::Weekdays => {
+            let mut date = from + chrono::Duration::days(1);
+            while matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+                date += chrono::Duration::days(1);
+            }
+            date
+        }
+        Repeat::Weekly(days) => {
+            (1..=7)
+                .map(|offset| from + chrono::Duration::days(offset))
+                .find(|date| days.contains(&date.weekday()))
+                .unwrap_or(from + chrono::Duration::days(7))
+        }
+        Repeat::Monthly(day) => {
+            // later this month if the day is still ahead, days past the month's end fall on its last day
+            let this_month = (*day).min(days_in_month(from.year(), from.month()));
+            if this_month > from.day() {
+                return from.with_day(this_month).unwrap();
+            }
+            let (year, month) = if from.month() == 12 { (from.year() + 1, 1) } else { (from.year(), from.month() + 1) };
+            NaiveDate::from_ymd_opt(year, month, (*day).min(days_in_month(year, month))).unwrap()
+        }
+    }
+}
+
+// Takes the recurrence off a finished task and returns the next instance, if the rule has one left
+fn next_occurrence(task: &mut Task) -> Option<Task> {
+    let recurrence = task.recurrence.take()?;
+    let from = if task.date_string.is_empty() { Local::now().date_naive() } else { task.due };
+    let due = next_due_date(&recurrence.rule, from);
+    let end = match recurrence.end {
+        RecurrenceEnd::Never => RecurrenceEnd::Never,
+        RecurrenceEnd::After(0) => return None,
+        RecurrenceEnd::After(n) => RecurrenceEnd::After(n - 1),
+        RecurrenceEnd::Until(until) if due > until => return None,
+        RecurrenceEnd::Until(until) => RecurrenceEnd::Until(until),
+    };
+
+    let mut next = task.clone();
+    next.id = Uuid::new_v4();
+    next.created_at = Local::now();
+    next.updated_at = next.created_at;
+    // keep the wall clock time, even across DST changes
+    next.due_at = task.due_at.and_then(|due_at| Local.from_local_datetime(&due.and_time(due_at.time())).earliest());
+    next.due = due;
+    next.date_string = short_date(due);
+    next.done = false;
+    next.completed_at = None;
+    for item in next.checklist.iter_mut() {
+        item.done = false;
+    }
+    next.recurrence = Some(Recurrence { rule: recurrence.rule, end });
+    Some(next)
+}
+
+fn short_date(date: NaiveDate) -> String {
+    format!("{}/{}", date.month0() + 1, date.day0() + 1)
+}
+
+/*** Task dependencies ***/
+fn find_task(taskboard: &TaskBoard, id: Uuid) -> Option<&Task> {
+    taskboard.lists.iter().flat_map(|list| list.tasks.iter()).find(|task| task.id == id)
+}
+
+// Unfinished tasks this task is waiting on, blockers that were deleted no longer count
+fn open_blockers<'a>(taskboard: &'a TaskBoard, task: &Task) -> Vec<&'a Task> {
+    task.blocked_by.iter()
+        .filter_map(|&id| find_task(taskboard, id))
+        .filter(|blocker| !blocker.done)
+        .collect()
+}
+
+fn is_blocked(taskboard: &TaskBoard, task: &Task) -> bool {
+    !task.done && !open_blockers(taskboard, task).is_empty()
+}
+
+// Checks that making `blocker` block `task` keeps the dependency graph acyclic
+fn check_dependency(taskboard: &TaskBoard, task: Uuid, blocker: Uuid) -> Result<(), DependencyError> {
+    if task == blocker {
+        return Err(DependencyError::SelfBlock);
+    }
+    // Depth first search from the blocker through its own blockers, looking for the task
+    let mut stack = vec![vec![blocker]];
+    let mut seen = BTreeSet::new();
+    while let Some(path) = stack.pop() {
+        let current = *path.last().unwrap();
+        if current == task {
+            let titles: Vec<String> = std::iter::once(task).chain(path)
+                .map(|id| find_task(taskboard, id).map(|t| t.title.clone()).unwrap_or_default())
+                .collect();
+            return Err(DependencyError::Cycle(titles.join(" -> ")));
+        }
+        if !seen.insert(current) {
+            continue;
+        }
+        if let Some(current_task) = find_task(taskboard, current) {
+            for &next in current_task.blocked_by.iter() {
+                let mut next_path = path.clone();
+                next_path.push(next);
+                stack.push(next_path);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Marks the selected task done or not done, finishing a blocked task needs `force`
+fn toggle_done(taskboard: &mut TaskBoard, force: bool) {
+    let Some(task) = selected_task(taskboard) else {
+        return;
+    };
+    if !task.done && !force {
+        let blockers: Vec<String> = open_blockers(taskboard, task).iter().map(|blocker| blocker.title.clone()).collect();
+        if !blockers.is_empty() {
+            taskboard.debug_str = format!("Blocked by {} (press X to complete anyway)", blockers.join(", "));
+            return;
+        }
+    }
+    let active_list = &mut taskboard.lists[taskboard.active_list - 1];
+    if let Some(task) = active_list.tasks.get_mut(active_list.selected) {
+        task.done = !task.done;
+        task.completed_at = if task.done { Some(Local::now()) } else { None };
+        if let Some(next) = task.done.then(|| next_occurrence(task)).flatten() {
+            active_list.tasks.push(next);
+        }
+    }
+    fix_selection(active_list, &taskboard.tag_filter);
+}
+
+// Deletes the selected task, a task hidden by a filter or by hide_completed is never selected
+fn delete_selected(taskboard: &mut TaskBoard) {
+    if selected_task(taskboard).is_none() {
+        return;
+    }
+    let active_list = &mut taskboard.lists[taskboard.active_list - 1];
+    let selected_task_index = active_list.selected;
+    let mut removed = active_list.tasks.remove(selected_task_index);
+    if let Some(next) = next_occurrence(&mut removed) {
+        active_list.tasks.push(next);
+    }
+    // subtasks of a deleted task move up a level instead of being lost
+    for task in active_list.tasks.iter_mut().filter(|task| task.parent == Some(removed.id)) {
+        task.parent = removed.parent;
+    }
+    let new_selected = match selected_task_index {
+        0 => 0,
+        len if len == active_list.tasks.len() => len - 1,
+        other => other,
+    };
+    active_list.selected = new_selected;
+    fix_selection(active_list, &taskboard.tag_filter);
+}
+
+/*** Task visibility ***/
+// Indices into list.tasks of the tasks that are currently rendered, in display order
+fn visible_tasks(list: &TaskList, tag_filter: &TagFilter) -> Vec<usize> {
+    list.tasks.iter()
+        .enumerate()
+        .filter(|(_, task)| !(list.hide_completed && task.done))
+        .filter(|(_, task)| !tag_filter.hide || tag_filter.matches(task))
+        .filter(|(i, _)| !hidden_by_collapse(list, *i))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// The selected task of the active list, if it is currently rendered
+fn selected_task(taskboard: &TaskBoard) -> Option<&Task> {
+    let list = taskboard.lists.get(taskboard.active_list.checked_sub(1)?)?;
+    if !visible_tasks(list, &taskboard.tag_filter).contains(&list.selected) {
+        return None;
+    }
+    list.tasks.get(list.selected)
+}
+
+fn selected_task_mut(taskboard: &mut TaskBoard) -> Option<&mut Task> {
+    selected_task(taskboard)?;
+    let list = &mut taskboard.lists[taskboard.active_list - 1];
+    list.tasks.get_mut(list.selected)
+}
+
+// j/k move within the active list, h/l and digits switch lists
+fn navigate(taskboard: &mut TaskBoard, c: char) {
+    if taskboard.num_lists == 0 {
+        return;
+    }
+    match c {
+        'j' => {
+            let active_list = &mut taskboard.lists[taskboard.active_list - 1];
+            if let Some(&next) = visible_tasks(active_list, &taskboard.tag_filter).iter().find(|&&i| i > active_list.selected) {
+                active_list.selected = next;
+            }
+        }
+        'k' => {
+            let active_list = &mut taskboard.lists[taskboard.active_list - 1];
+            if let Some(&prev) = visible_tasks(active_list, &taskboard.tag_filter).iter().rev().find(|&&i| i < active_list.selected) {
+                active_list.selected = prev;
+            }
+        }
+        'h' | 'l' | '1'..='9' => {
+            let new_active_list = match c {
+                'h' if taskboard.active_list > 1 => taskboard.active_list - 1,
+                'l' if taskboard.active_list < taskboard.num_lists => taskboard.active_list + 1,
+                '1'..='9' => c.to_digit(10).map(|n| n as usize).unwrap_or(1).min(taskboard.num_lists),
+                _ => taskboard.active_list,
+            };
+            taskboard.active_list = new_active_list;
+            taskboard.lists[taskboard.active_list - 1].selected = 0;
+            fix_selection(&mut taskboard.lists[taskboard.active_list - 1], &taskboard.tag_filter);
+        }
+        _ => {}
+    }
+}
+
+// Move the selection onto a visible task, preferring the next one down
+fn fix_selection(list: &mut TaskList, tag_filter: &TagFilter) {
+    let visible = visible_tasks(list, tag_filter);
+    list.selected = visible.iter()
+        .find(|&&i| i >= list.selected)
+        .or(visible.last())
+        .copied()
+        .unwrap_or(0);
+}
+fn get_helpline() -> Line<'static>{
+    Line::from(vec![
+        Span::styled(
+            "<num>",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Select List - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "N",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "ew List - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "D",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "elete List - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "A",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "dd item - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "d",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "elete item - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "x",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Toggle done - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "c",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Hide/show done - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "e",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "dit notes - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "+/-",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Priority - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "t",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "ags - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "f",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "ilter tags (",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "F",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " hide) - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "S",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "ubtask - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            ">/<",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Indent/outdent - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "z",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Fold - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "b",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "locked by (",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "B",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " clear) - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "r",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "epeat - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "C",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "hecklist - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "u/U",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Undo/Redo - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "O",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "pen Board - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "H",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "istory - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "Q",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "uit",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        ]
+    )
+}
+
+fn get_notes_helpline() -> Line<'static>{
+    Line::from(vec![
+        Span::styled(
+            "Enter",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " New line - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "Esc",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Save notes",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        ]
+    )
+}
+
+fn get_checklist_helpline() -> Line<'static>{
+    Line::from(vec![
+        Span::styled(
+            "a",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "dd step - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "x",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Toggle step - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "J/K",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Move step - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "d",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "elete step - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "Esc",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Back",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        ]
+    )
+}
+
+fn get_boards_helpline() -> Line<'static>{
+    Line::from(vec![
+        Span::styled(
+            "Enter",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
         ),
         Span::styled(
-            "elete item - ",
+            " Open - ",
             Style::default()
                 .fg(COLOR2)
         ),
         Span::styled(
-            "Q",
+            "n",
             Style::default()
                 .fg(COLOR1)
                 .add_modifier(Modifier::UNDERLINED),
         ),
         Span::styled(
-            "uit",
+            "ew board - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "r",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "ename - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "D",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            "elete - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "Esc",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Back",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        ]
+    )
+}
+
+fn get_history_helpline() -> Line<'static>{
+    Line::from(vec![
+        Span::styled(
+            "j/k",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Scroll - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "Tab",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Task/List - ",
+            Style::default()
+                .fg(COLOR2)
+        ),
+        Span::styled(
+            "Esc",
+            Style::default()
+                .fg(COLOR1)
+                .add_modifier(Modifier::UNDERLINED),
+        ),
+        Span::styled(
+            " Back",
             Style::default()
                 .fg(COLOR2)
         ),
@@ -416,61 +2113,364 @@ fn get_helpline() -> Line<'static>{
 }
 
 /*** Key input handling ***/
-fn handle_events(active_menu_item: &mut MenuItem, taskboard: &mut TaskBoard) -> io::Result<bool> {
-    if event::poll(std::time::Duration::from_millis(50))? {
+fn handle_events(active_menu_item: &mut MenuItem, taskboard: &mut TaskBoard, db_path: &Path) -> io::Result<bool> {
+    // run already waited for the event
+    if event::poll(Duration::ZERO)? {
         if let Event::Key(key) = event::read()? {
             match active_menu_item {
 
+                /*** Picking a board ***/
+                MenuItem::PickingBoard => {
+                    let selected = taskboard.boards.get(taskboard.board_selected).cloned();
+                    match key.code {
+                        KeyCode::Char('j') | KeyCode::Down if taskboard.board_selected + 1 < taskboard.boards.len() => taskboard.board_selected += 1,
+                        KeyCode::Char('k') | KeyCode::Up => taskboard.board_selected = taskboard.board_selected.saturating_sub(1),
+                        KeyCode::Enter => {
+                            taskboard.board_action = selected.map(BoardAction::Open);
+                            *active_menu_item = MenuItem::Home;
+                        }
+                        KeyCode::Char('n') => {
+                            taskboard.renaming_board = None;
+                            taskboard.input = String::from("|");
+                            *active_menu_item = MenuItem::NamingBoard;
+                        }
+                        KeyCode::Char('r') => {
+                            taskboard.renaming_board = selected;
+                            taskboard.input = String::from("|");
+                            *active_menu_item = MenuItem::NamingBoard;
+                        }
+                        KeyCode::Char('D') if selected.is_some() => *active_menu_item = MenuItem::DeletingBoard,
+                        KeyCode::Esc => *active_menu_item = MenuItem::Home,
+                        _ => {}
+                    }
+                    return Ok(false);
+                }
+
+                /*** Confirming that a board should be deleted ***/
+                MenuItem::DeletingBoard => {
+                    match key.code {
+                        KeyCode::Char('y') | KeyCode::Char('Y') => {
+                            taskboard.board_action = taskboard.boards.get(taskboard.board_selected).cloned().map(BoardAction::Delete);
+                            *active_menu_item = MenuItem::Home;
+                        }
+                        _ => *active_menu_item = MenuItem::PickingBoard,
+                    }
+                    return Ok(false);
+                }
+
+                /*** Viewing the history of a task or list ***/
+                MenuItem::ViewingHistory => {
+                    let lines = taskboard.timeline.as_ref().map_or(0, |lines| lines.len()) as u16;
+                    match key.code {
+                        KeyCode::Char('j') | KeyCode::Down if taskboard.timeline_scroll + 1 < lines => taskboard.timeline_scroll += 1,
+                        KeyCode::Char('k') | KeyCode::Up => taskboard.timeline_scroll = taskboard.timeline_scroll.saturating_sub(1),
+                        KeyCode::Tab => {
+                            taskboard.timeline_list = !taskboard.timeline_list;
+                            taskboard.timeline = None;
+                            taskboard.timeline_scroll = 0;
+                        }
+                        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('H') => {
+                            taskboard.timeline = None;
+                            *active_menu_item = MenuItem::Home;
+                        }
+                        _ => {}
+                    }
+                    return Ok(false);
+                }
+
+                /*** Naming a new or renamed board ***/
+                MenuItem::NamingBoard => {
+                    match key.code {
+                        KeyCode::Char(c) => {
+                            taskboard.input.pop();
+                            taskboard.input.push(c);
+                            taskboard.input.push('|');
+                        }
+                        KeyCode::Backspace => {
+                            taskboard.input.pop();
+                            taskboard.input.pop();
+                            taskboard.input.push('|');
+                        }
+                        KeyCode::Enter => {
+                            taskboard.input.pop();
+                            let name = taskboard.input.trim().to_string();
+                            taskboard.board_action = Some(match taskboard.renaming_board.take() {
+                                Some(from) => BoardAction::Rename(from, name),
+                                None => BoardAction::Create(name),
+                            });
+                            *active_menu_item = MenuItem::Home;
+                        }
+                        KeyCode::Esc => *active_menu_item = MenuItem::PickingBoard,
+                        _ => {}
+                    }
+                    return Ok(false);
+                }
+
+                /*** Editing notes ***/
+                MenuItem::EditingNotes => {
+                    let Some(task) = selected_task_mut(taskboard) else {
+                        *active_menu_item = MenuItem::Home;
+                        return Ok(false);
+                    };
+                    // notes carry a trailing '|' cursor while being edited
+                    match key.code {
+                        KeyCode::Char(c) => {
+                            task.notes.pop();
+                            task.notes.push(c);
+                            task.notes.push('|');
+                        }
+                        KeyCode::Enter => {
+                            task.notes.pop();
+                            task.notes.push('\n');
+                            task.notes.push('|');
+                        }
+                        KeyCode::Backspace => {
+                            task.notes.pop();
+                            task.notes.pop();
+                            task.notes.push('|');
+                        }
+                        KeyCode::Esc => {
+                            task.notes.pop();
+                            *active_menu_item = MenuItem::Home;
+                        }
+                        _ => {}
+                    }
+                    return Ok(false);
+                }
+
+                /*** Editing tags and the tag filter ***/
+                MenuItem::EditingTags | MenuItem::FilteringTags => {
+                    match key.code {
+                        KeyCode::Char(c) => {
+                            taskboard.input.pop();
+                            taskboard.input.push(c);
+                            taskboard.input.push('|');
+                        }
+                        KeyCode::Backspace => {
+                            taskboard.input.pop();
+                            taskboard.input.pop();
+                            taskboard.input.push('|');
+                        }
+                        KeyCode::Enter => {
+                            taskboard.input.pop();
+                            let tags = parse_tags(&taskboard.input);
+                            if let MenuItem::EditingTags = active_menu_item {
+                                if let Some(task) = selected_task_mut(taskboard) {
+                                    task.tags = tags;
+                                }
+                            } else {
+                                taskboard.debug_str = if tags.is_empty() {
+                                    "Tag filter cleared".to_string()
+                                } else {
+                                    format!("Filtering by #{}", tags.iter().cloned().collect::<Vec<_>>().join(" #"))
+                                };
+                                taskboard.tag_filter.tags = tags;
+                                for list in taskboard.lists.iter_mut() {
+                                    fix_selection(list, &taskboard.tag_filter);
+                                }
+                            }
+                            *active_menu_item = MenuItem::Home;
+                        }
+                        KeyCode::Esc => *active_menu_item = MenuItem::Home,
+                        _ => {}
+                    }
+                    return Ok(false);
+                }
+
+                /*** Picking a blocking task ***/
+                MenuItem::PickingBlocker => {
+                    match key.code {
+                        KeyCode::Char(c) => navigate(taskboard, c),
+                        KeyCode::Enter => {
+                            *active_menu_item = MenuItem::Home;
+                            let (Some(task_id), Some(blocker_id)) = (taskboard.linking.take(), selected_task(taskboard).map(|task| task.id)) else {
+                                return Ok(false);
+                            };
+                            let Some(task) = taskboard.lists.iter_mut().flat_map(|list| list.tasks.iter_mut()).find(|task| task.id == task_id) else {
+                                return Ok(false);
+                            };
+                            if let Some(position) = task.blocked_by.iter().position(|&id| id == blocker_id) {
+                                task.blocked_by.remove(position);
+                                return Ok(false);
+                            }
+                            match check_dependency(taskboard, task_id, blocker_id) {
+                                Ok(()) => {
+                                    if let Some(task) = taskboard.lists.iter_mut().flat_map(|list| list.tasks.iter_mut()).find(|task| task.id == task_id) {
+                                        task.blocked_by.push(blocker_id);
+                                    }
+                                }
+                                Err(err) => taskboard.debug_str = format!("Cannot link tasks: {}", err),
+                            }
+                        }
+                        KeyCode::Esc => {
+                            taskboard.linking = None;
+                            *active_menu_item = MenuItem::Home;
+                        }
+                        _ => {}
+                    }
+                    return Ok(false);
+                }
+
+                /*** Editing recurrence ***/
+                MenuItem::EditingRecurrence => {
+                    match key.code {
+                        KeyCode::Char(c) => {
+                            taskboard.input.pop();
+                            taskboard.input.push(c);
+                            taskboard.input.push('|');
+                        }
+                        KeyCode::Backspace => {
+                            taskboard.input.pop();
+                            taskboard.input.pop();
+                            taskboard.input.push('|');
+                        }
+                        KeyCode::Enter => {
+                            taskboard.input.pop();
+                            let recurrence = match taskboard.input.trim() {
+                                "" => Ok(None),
+                                input => input.parse::<Recurrence>().map(Some),
+                            };
+                            match recurrence {
+                                Ok(recurrence) => {
+                                    if let Some(task) = selected_task_mut(taskboard) {
+                                        task.recurrence = recurrence;
+                                    }
+                                }
+                                Err(err) => taskboard.debug_str = format!("Invalid repeat rule: {}", err),
+                            }
+                            *active_menu_item = MenuItem::Home;
+                        }
+                        KeyCode::Esc => *active_menu_item = MenuItem::Home,
+                        _ => {}
+                    }
+                    return Ok(false);
+                }
+
+                /*** Checklist ***/
+                MenuItem::Checklist => {
+                    let selected = taskboard.checklist_selected;
+                    let Some(task) = selected_task_mut(taskboard) else {
+                        *active_menu_item = MenuItem::Home;
+                        return Ok(false);
+                    };
+                    let checklist = &mut task.checklist;
+                    let mut new_selected = selected;
+                    match key.code {
+                        KeyCode::Char('a') => {
+                            checklist.push(ChecklistItem{text: String::from("|"), done: false});
+                            new_selected = checklist.len() - 1;
+                            *active_menu_item = MenuItem::AddingChecklistItem;
+                        }
+                        KeyCode::Char('x') | KeyCode::Char(' ') => {
+                            if let Some(item) = checklist.get_mut(selected) {
+                                item.done = !item.done;
+                            }
+                        }
+                        KeyCode::Char('d') if selected < checklist.len() => {
+                            checklist.remove(selected);
+                        }
+                        KeyCode::Char('j') => new_selected += 1,
+                        KeyCode::Char('k') => new_selected = selected.saturating_sub(1),
+                        KeyCode::Char('J') if selected + 1 < checklist.len() => {
+                            checklist.swap(selected, selected + 1);
+                            new_selected += 1;
+                        }
+                        KeyCode::Char('K') if selected > 0 && selected < checklist.len() => {
+                            checklist.swap(selected, selected - 1);
+                            new_selected -= 1;
+                        }
+                        KeyCode::Esc => *active_menu_item = MenuItem::Home,
+                        _ => {}
+                    }
+                    taskboard.checklist_selected = new_selected.min(checklist.len().saturating_sub(1));
+                    return Ok(false);
+                }
+
+                /*** Adding checklist item ***/
+                MenuItem::AddingChecklistItem => {
+                    let selected = taskboard.checklist_selected;
+                    let Some(item) = selected_task_mut(taskboard).and_then(|task| task.checklist.get_mut(selected)) else {
+                        *active_menu_item = MenuItem::Home;
+                        return Ok(false);
+                    };
+                    match key.code {
+                        KeyCode::Char(c) => {
+                            item.text.pop();
+                            item.text.push(c);
+                            item.text.push('|');
+                        }
+                        KeyCode::Backspace => {
+                            item.text.pop();
+                            item.text.pop();
+                            item.text.push('|');
+                        }
+                        KeyCode::Enter => {
+                            item.text.pop();
+                            *active_menu_item = MenuItem::Checklist;
+                        }
+                        KeyCode::Esc => {
+                            if let Some(task) = selected_task_mut(taskboard) {
+                                task.checklist.remove(selected);
+                                taskboard.checklist_selected = task.checklist.len().saturating_sub(1);
+                            }
+                            *active_menu_item = MenuItem::Checklist;
+                        }
+                        _ => {}
+                    }
+                    return Ok(false);
+                }
+
                 /*** Adding task date ***/
                 MenuItem::AddingTaskDate => {
                     // make inputs change list name
                     if key.kind == event::KeyEventKind::Press && key.code == KeyCode::Enter {
                         *active_menu_item = MenuItem::Home;
+                        let adding_task_index = taskboard.lists[taskboard.active_list - 1].selected;
                         let tasks = &mut taskboard.lists[taskboard.active_list - 1].tasks;
-                        let last_task_index = tasks.len() - 1;
-                        if let Some(last_task) = tasks.get_mut(last_task_index) {
-                            let mut new_task = last_task.clone();
+                        if let Some(adding_task) = tasks.get_mut(adding_task_index) {
+                            let mut new_task = adding_task.clone();
                             new_task.date_string.pop();
-                            if let Ok(due_date) = NaiveDate::parse_from_str(&new_task.date_string, "%Y/%m/%d") {
+                            if let Some((due_date, due_at)) = parse_due(&new_task.date_string) {
                                 new_task.due = due_date;
-                                new_task.date_string = format!("{}/{}", due_date.month0() + 1, due_date.day0() + 1);
+                                new_task.due_at = due_at;
+                                new_task.date_string = short_date(due_date);
                             } else if new_task.date_string == String::new(){
                                 taskboard.debug_str = "Empty Date".to_string();
                             } else {
                                 taskboard.debug_str = format!("Failed to parse date: {}", new_task.date_string);
                             }
-                            *last_task = new_task;
+                            *adding_task = new_task;
                             return Ok(false);
                         }
                     }
                     if let KeyCode::Char(c) = key.code {
+                        let adding_task_index = taskboard.lists[taskboard.active_list - 1].selected;
                         let tasks = &mut taskboard.lists[taskboard.active_list - 1].tasks;
-                        let last_task_index = tasks.len() - 1;
-                        if let Some(last_task) = tasks.get_mut(last_task_index) {
-                            let mut new_task = last_task.clone();
+                        if let Some(adding_task) = tasks.get_mut(adding_task_index) {
+                            let mut new_task = adding_task.clone();
                             new_task.date_string.insert(new_task.date_string.len() - 1 ,c);
-                            *last_task = new_task;
+                            *adding_task = new_task;
                             return Ok(false);
                         }
                     }
                     if key.code == KeyCode::Backspace{
+                        let adding_task_index = taskboard.lists[taskboard.active_list - 1].selected;
                         let tasks = &mut taskboard.lists[taskboard.active_list - 1].tasks;
-                        let last_task_index = tasks.len() - 1;
-                        if let Some(last_task) = tasks.get_mut(last_task_index) {
-                            let mut new_task = last_task.clone();
+                        if let Some(adding_task) = tasks.get_mut(adding_task_index) {
+                            let mut new_task = adding_task.clone();
                             if new_task.date_string.len() != 1{
                                 new_task.date_string.remove(new_task.date_string.len() - 2);
                             }
-                            *last_task = new_task;
+                            *adding_task = new_task;
                             return Ok(false);
                         }
                     }
                     if key.code == KeyCode::Esc{
-                        taskboard.lists[taskboard.active_list - 1].tasks.pop();
-                        taskboard.lists[taskboard.active_list - 1].selected = match taskboard.lists[taskboard.active_list - 1].tasks.len(){
-                            0 => 0,
-                            _=> taskboard.lists[taskboard.active_list - 1].tasks.len() - 1,
-                        };
+                        let active_list = &mut taskboard.lists[taskboard.active_list - 1];
+                        if active_list.selected < active_list.tasks.len() {
+                            active_list.tasks.remove(active_list.selected);
+                        }
+                        fix_selection(active_list, &taskboard.tag_filter);
                         *active_menu_item = MenuItem::Home;
                     }
                 }
@@ -480,43 +2480,43 @@ fn handle_events(active_menu_item: &mut MenuItem, taskboard: &mut TaskBoard) ->
                     // make inputs change list name
                     if key.kind == event::KeyEventKind::Press && key.code == KeyCode::Enter {
                         *active_menu_item = MenuItem::AddingTaskDate;
+                        let adding_task_index = taskboard.lists[taskboard.active_list - 1].selected;
                         let tasks = &mut taskboard.lists[taskboard.active_list - 1].tasks;
-                        let last_task_index = tasks.len() - 1;
-                        if let Some(last_task) = tasks.get_mut(last_task_index) {
-                            let mut new_task = last_task.clone();
+                        if let Some(adding_task) = tasks.get_mut(adding_task_index) {
+                            let mut new_task = adding_task.clone();
                             new_task.title.pop();
-                            *last_task = new_task;
+                            *adding_task = new_task;
                             return Ok(false);
                         }
                     }
                     if let KeyCode::Char(c) = key.code {
+                        let adding_task_index = taskboard.lists[taskboard.active_list - 1].selected;
                         let tasks = &mut taskboard.lists[taskboard.active_list - 1].tasks;
-                        let last_task_index = tasks.len() - 1;
-                        if let Some(last_task) = tasks.get_mut(last_task_index) {
-                            let mut new_task = last_task.clone();
+                        if let Some(adding_task) = tasks.get_mut(adding_task_index) {
+                            let mut new_task = adding_task.clone();
                             new_task.title.insert(new_task.title.len() - 1 ,c);
-                            *last_task = new_task;
+                            *adding_task = new_task;
                             return Ok(false);
                         }
                     }
                     if key.code == KeyCode::Backspace{
+                        let adding_task_index = taskboard.lists[taskboard.active_list - 1].selected;
                         let tasks = &mut taskboard.lists[taskboard.active_list - 1].tasks;
-                        let last_task_index = tasks.len() - 1;
-                        if let Some(last_task) = tasks.get_mut(last_task_index) {
-                            let mut new_task = last_task.clone();
+                        if let Some(adding_task) = tasks.get_mut(adding_task_index) {
+                            let mut new_task = adding_task.clone();
                             if new_task.title.len() != 1{
                                 new_task.title.remove(new_task.title.len() - 2);
                             }
-                            *last_task = new_task;
+                            *adding_task = new_task;
                             return Ok(false);
                         }
                     }
                     if key.code == KeyCode::Esc{
-                        taskboard.lists[taskboard.active_list - 1].tasks.pop();
-                        taskboard.lists[taskboard.active_list - 1].selected = match taskboard.lists[taskboard.active_list - 1].tasks.len(){
-                            0 => 0,
-                            _=> taskboard.lists[taskboard.active_list - 1].tasks.len() - 1,
-                        };
+                        let active_list = &mut taskboard.lists[taskboard.active_list - 1];
+                        if active_list.selected < active_list.tasks.len() {
+                            active_list.tasks.remove(active_list.selected);
+                        }
+                        fix_selection(active_list, &taskboard.tag_filter);
                         *active_menu_item = MenuItem::Home;
                     }
                 }
@@ -551,34 +2551,154 @@ fn handle_events(active_menu_item: &mut MenuItem, taskboard: &mut TaskBoard) ->
                     if let KeyCode::Char(c) = key.code {
                         match c {
                             'q' => return Ok(true),
+                            'u' => {
+                                undo(taskboard);
+                                return Ok(false);
+                            }
+                            'U' => {
+                                redo(taskboard);
+                                return Ok(false);
+                            }
+                            'H' => {
+                                // the selected task's history, or the list's when nothing is selected
+                                taskboard.timeline = None;
+                                taskboard.timeline_list = selected_task(taskboard).is_none();
+                                taskboard.timeline_scroll = 0;
+                                *active_menu_item = MenuItem::ViewingHistory;
+                                return Ok(false);
+                            }
+                            'o' => {
+                                match list_boards(db_path) {
+                                    Ok(boards) => {
+                                        taskboard.board_selected = boards.iter().position(|name| *name == taskboard.board_name).unwrap_or(0);
+                                        taskboard.boards = boards;
+                                        *active_menu_item = MenuItem::PickingBoard;
+                                    }
+                                    Err(err) => taskboard.debug_str = format!("Could not list boards: {}", err),
+                                }
+                                return Ok(false);
+                            }
                             'n' => {
                                 create_list(taskboard);
                                 taskboard.active_list = taskboard.lists.len();
                                 *active_menu_item = MenuItem::AddingList;
                                 return Ok(false);
                             }
-                            'a' => {
+                            'a' | 'S' => {
+                                let parent = match c {
+                                    'S' => match selected_task_mut(taskboard) {
+                                        Some(task) => {
+                                            task.collapsed = false;
+                                            Some(task.id)
+                                        }
+                                        None => return Ok(false),
+                                    },
+                                    _ => None,
+                                };
                                 if taskboard.num_lists > 0 {
-                                    taskboard.lists[taskboard.active_list - 1].tasks.push(Task{title: String::from("|"), due: NaiveDate::from_ymd_opt(2102, 12, 1).unwrap(), date_string: String::from("|")});
+                                    let mut new_task = Task::new(String::from("|"));
+                                    new_task.parent = parent;
+                                    new_task.date_string = String::from("|");
+                                    taskboard.lists[taskboard.active_list - 1].tasks.push(new_task);
                                     taskboard.lists[taskboard.active_list - 1].selected = taskboard.lists[taskboard.active_list - 1].tasks.len() - 1;
                                     *active_menu_item = MenuItem::AddingTaskTitle;
                                 }
                                 return Ok(false);
                             }
                             'd' => {
-                                let active_list_index = taskboard.active_list - 1;
-                                let active_list = &mut taskboard.lists[active_list_index];
-                                if active_list.tasks.is_empty(){
+                                delete_selected(taskboard);
+                                return Ok(false);
+                            }
+                            'x' | 'X' => {
+                                toggle_done(taskboard, c == 'X');
+                                return Ok(false);
+                            }
+                            '>' | '<' => {
+                                indent_selected(taskboard, c == '>');
+                                return Ok(false);
+                            }
+                            'z' => {
+                                if let Some(id) = selected_task(taskboard).map(|task| task.id) {
+                                    let active_list = &mut taskboard.lists[taskboard.active_list - 1];
+                                    if has_subtasks(active_list, id) {
+                                        let selected = active_list.selected;
+                                        active_list.tasks[selected].collapsed = !active_list.tasks[selected].collapsed;
+                                    }
+                                }
+                                return Ok(false);
+                            }
+                            'b' => {
+                                if let Some(task) = selected_task(taskboard) {
+                                    taskboard.linking = Some(task.id);
+                                    *active_menu_item = MenuItem::PickingBlocker;
+                                }
+                                return Ok(false);
+                            }
+                            'B' => {
+                                if let Some(task) = selected_task_mut(taskboard) {
+                                    task.blocked_by.clear();
+                                }
+                                return Ok(false);
+                            }
+                            'e' => {
+                                if let Some(task) = selected_task_mut(taskboard) {
+                                    task.notes.push('|');
+                                    *active_menu_item = MenuItem::EditingNotes;
+                                }
+                                return Ok(false);
+                            }
+                            '+' | '=' | '-' => {
+                                if let Some(task) = selected_task_mut(taskboard) {
+                                    task.priority = match c {
+                                        '-' => task.priority.lower(),
+                                        _ => task.priority.raise(),
+                                    };
+                                }
+                                return Ok(false);
+                            }
+                            't' => {
+                                if let Some(task) = selected_task(taskboard) {
+                                    taskboard.input = task.tags.iter().cloned().map(|tag| tag + " ").collect::<String>();
+                                    taskboard.input.push('|');
+                                    *active_menu_item = MenuItem::EditingTags;
+                                }
+                                return Ok(false);
+                            }
+                            'f' => {
+                                taskboard.input = taskboard.tag_filter.tags.iter().cloned().map(|tag| tag + " ").collect::<String>();
+                                taskboard.input.push('|');
+                                *active_menu_item = MenuItem::FilteringTags;
+                                return Ok(false);
+                            }
+                            'F' => {
+                                taskboard.tag_filter.hide = !taskboard.tag_filter.hide;
+                                for list in taskboard.lists.iter_mut() {
+                                    fix_selection(list, &taskboard.tag_filter);
+                                }
+                                return Ok(false);
+                            }
+                            'r' => {
+                                if let Some(task) = selected_task(taskboard) {
+                                    taskboard.input = task.recurrence.as_ref().map(|r| r.to_string()).unwrap_or_default();
+                                    taskboard.input.push('|');
+                                    *active_menu_item = MenuItem::EditingRecurrence;
+                                }
+                                return Ok(false);
+                            }
+                            'C' => {
+                                if selected_task(taskboard).is_some() {
+                                    taskboard.checklist_selected = 0;
+                                    *active_menu_item = MenuItem::Checklist;
+                                }
+                                return Ok(false);
+                            }
+                            'c' => {
+                                if taskboard.num_lists == 0 {
                                     return Ok(false);
                                 }
-                                let selected_task_index = active_list.selected;
-                                active_list.tasks.remove(selected_task_index);
-                                let new_selected = match selected_task_index {
-                                    0 => 0,
-                                    len if len == active_list.tasks.len() => len - 1,
-                                    other => other,
-                                };
-                                active_list.selected = new_selected;
+                                let active_list = &mut taskboard.lists[taskboard.active_list - 1];
+                                active_list.hide_completed = !active_list.hide_completed;
+                                fix_selection(active_list, &taskboard.tag_filter);
                                 return Ok(false);
                             }
                             'D' => {
@@ -588,29 +2708,10 @@ fn handle_events(active_menu_item: &mut MenuItem, taskboard: &mut TaskBoard) ->
                                     1 => 1,
                                     _=> taskboard.active_list - 1,
                                 };
-                                for (i, list) in taskboard.lists.iter_mut().enumerate() {
-                                    list.id = i + 1;
-                                }
-                                taskboard.active_list = new_active_list;
-                                return Ok(false);
-                            }
-                            'j' => if taskboard.lists[taskboard.active_list - 1].selected + 1< taskboard.lists[taskboard.active_list - 1].tasks.len(){
-                                taskboard.lists[taskboard.active_list - 1].selected += 1;
-                            }
-                            'k' => if taskboard.lists[taskboard.active_list - 1].selected > 0{
-                                taskboard.lists[taskboard.active_list - 1].selected -= 1;
-                            }
-                            'h' | 'l' | '0'..='9' => {
-                                let new_active_list = match c {
-                                    'h' if taskboard.active_list > 1 => taskboard.active_list - 1,
-                                    'l' if taskboard.active_list < taskboard.num_lists => taskboard.active_list + 1,
-                                    '0'..='9' => c.to_digit(10).map(|n| n as usize).unwrap_or(0),
-                                    _ => taskboard.active_list,
-                                };
                                 taskboard.active_list = new_active_list;
-                                taskboard.lists[taskboard.active_list - 1].selected = 0;
                                 return Ok(false);
                             }
+                            'j' | 'k' | 'h' | 'l' | '0'..='9' => navigate(taskboard, c),
                             _ => {}
                         }
                     }
@@ -620,3 +2721,185 @@ fn handle_events(active_menu_item: &mut MenuItem, taskboard: &mut TaskBoard) ->
     }
     Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.from_local_datetime(&date(year, month, day).and_hms_opt(hour, minute, 0).unwrap()).earliest().unwrap()
+    }
+
+    /*** Due times ***/
+    #[test]
+    fn parse_due_reads_dates_and_deadlines() {
+        assert_eq!(parse_due("2024/05/03"), Some((date(2024, 5, 3), None)));
+        assert_eq!(parse_due(" 2024/05/03 17:30 "), Some((date(2024, 5, 3), Some(local(2024, 5, 3, 17, 30)))));
+        let (due, due_at) = parse_due("09:15").unwrap();
+        assert_eq!(due, Local::now().date_naive());
+        assert_eq!(due_at.unwrap().time(), NaiveTime::from_hms_opt(9, 15, 0).unwrap());
+    }
+
+    #[test]
+    fn parse_due_rejects_other_input() {
+        for input in ["", "tomorrow", "2024-05-03", "2024/13/01", "2024/05/03 25:00", "5pm"] {
+            assert_eq!(parse_due(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn due_time_label_counts_down_to_the_deadline() {
+        let now = local(2024, 5, 3, 8, 0);
+        assert_eq!(due_time_label(local(2024, 5, 3, 7, 59), now), "Overdue");
+        assert_eq!(due_time_label(local(2024, 5, 3, 8, 45), now), "in 45m");
+        assert_eq!(due_time_label(local(2024, 5, 3, 10, 0), now), "in 2h");
+        assert_eq!(due_time_label(local(2024, 5, 3, 17, 0), now), "due 17:00");
+        assert_eq!(due_time_label(local(2024, 5, 4, 9, 30), now), "Tomorrow 09:30");
+        assert_eq!(due_time_label(local(2024, 10, 21, 17, 0), now), "10/21 17:00");
+    }
+
+    /*** Task dependencies ***/
+    // A board with one list holding tasks of the given titles, and their ids
+    fn board_with(titles: &[&str]) -> (TaskBoard, Vec<Uuid>) {
+        let mut list = transfer::new_list("Work");
+        list.tasks = titles.iter().map(|title| Task::new(title.to_string())).collect();
+        let ids = list.tasks.iter().map(|task| task.id).collect();
+        let db = DbFile { lists: vec![list], ..DbFile::default() };
+        (new_taskboard(db, MAIN_BOARD.to_string(), String::new(), History::default()), ids)
+    }
+
+    #[test]
+    fn check_dependency_allows_chains() {
+        let (mut taskboard, ids) = board_with(&["Ship", "Test", "Build"]);
+        taskboard.lists[0].tasks[0].blocked_by = vec![ids[1]];
+        assert!(check_dependency(&taskboard, ids[1], ids[2]).is_ok());
+        // a second path to the same blocker is not a cycle
+        taskboard.lists[0].tasks[1].blocked_by = vec![ids[2]];
+        assert!(check_dependency(&taskboard, ids[0], ids[2]).is_ok());
+    }
+
+    #[test]
+    fn check_dependency_rejects_self_blocks_and_cycles() {
+        let (mut taskboard, ids) = board_with(&["Ship", "Test", "Build"]);
+        assert!(matches!(check_dependency(&taskboard, ids[0], ids[0]), Err(DependencyError::SelfBlock)));
+        taskboard.lists[0].tasks[0].blocked_by = vec![ids[1]];
+        taskboard.lists[0].tasks[1].blocked_by = vec![ids[2]];
+        match check_dependency(&taskboard, ids[2], ids[0]) {
+            Err(DependencyError::Cycle(path)) => assert_eq!(path, "Build -> Ship -> Test -> Build"),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deleted_blockers_do_not_block() {
+        let (mut taskboard, ids) = board_with(&["Ship", "Test"]);
+        taskboard.lists[0].tasks[0].blocked_by = vec![ids[1], Uuid::new_v4()];
+        assert!(is_blocked(&taskboard, &taskboard.lists[0].tasks[0]));
+        taskboard.lists[0].tasks[1].done = true;
+        assert!(!is_blocked(&taskboard, &taskboard.lists[0].tasks[0]));
+    }
+
+    /*** Deleting tasks ***/
+    #[test]
+    fn delete_skips_hidden_tasks() {
+        let (mut taskboard, ids) = board_with(&["Report", "Review"]);
+        taskboard.active_list = 1;
+        taskboard.lists[0].tasks[0].done = true;
+        taskboard.lists[0].hide_completed = true;
+        taskboard.lists[0].tasks[1].tags.insert("later".to_string());
+        taskboard.tag_filter = TagFilter { tags: BTreeSet::from(["today".to_string()]), hide: true };
+        fix_selection(&mut taskboard.lists[0], &taskboard.tag_filter);
+        delete_selected(&mut taskboard);
+        assert_eq!(taskboard.lists[0].tasks.len(), 2);
+
+        // once the filter is off the visible task is the one deleted
+        taskboard.tag_filter.hide = false;
+        fix_selection(&mut taskboard.lists[0], &taskboard.tag_filter);
+        delete_selected(&mut taskboard);
+        let left: Vec<Uuid> = taskboard.lists[0].tasks.iter().map(|task| task.id).collect();
+        assert_eq!(left, [ids[0]]);
+    }
+
+    /*** Leaving an edit on exit ***/
+    #[test]
+    fn discard_edit_drops_only_what_is_being_typed() {
+        let (mut taskboard, ids) = board_with(&["Report", "Review"]);
+        taskboard.active_list = 1;
+        let report = &mut taskboard.lists[0].tasks[0];
+        report.checklist = vec![ChecklistItem { text: "Outline".to_string(), done: true }, ChecklistItem { text: "Dra|".to_string(), done: false }];
+        taskboard.checklist_selected = 1;
+        discard_edit(&mut taskboard, MenuItem::AddingChecklistItem);
+        let checklist = &taskboard.lists[0].tasks[0].checklist;
+        assert_eq!((checklist.len(), checklist[0].done), (1, true));
+
+        taskboard.lists[0].tasks[0].notes = "first line\nsecond|".to_string();
+        discard_edit(&mut taskboard, MenuItem::EditingNotes);
+        assert_eq!(taskboard.lists[0].tasks[0].notes, "first line\nsecond");
+
+        taskboard.lists[0].tasks.push(Task::new("Sh|".to_string()));
+        taskboard.lists[0].selected = 2;
+        discard_edit(&mut taskboard, MenuItem::AddingTaskTitle);
+        let left: Vec<Uuid> = taskboard.lists[0].tasks.iter().map(|task| task.id).collect();
+        assert_eq!(left, ids);
+
+        taskboard.lists.push(transfer::new_list("Ho|"));
+        (taskboard.num_lists, taskboard.active_list) = (2, 2);
+        taskboard.input = "urgent|".to_string();
+        discard_edit(&mut taskboard, MenuItem::AddingList);
+        assert_eq!((taskboard.lists.len(), taskboard.num_lists, taskboard.active_list), (1, 1, 1));
+        assert!(taskboard.input.is_empty());
+
+        // finished work in other modes is kept as it is
+        let before = taskboard.lists.clone();
+        discard_edit(&mut taskboard, MenuItem::Checklist);
+        assert!(taskboard.lists == before);
+    }
+
+    /*** Recurrence ***/
+    #[test]
+    fn recurrence_round_trips_through_display() {
+        for rule in ["daily", "weekdays", "weekly mon,thu", "monthly 31", "every 3 days", "weekly fri until 2024/06/01", "daily for 4 times"] {
+            let recurrence: Recurrence = rule.parse().unwrap();
+            assert_eq!(recurrence.to_string().parse::<Recurrence>().unwrap(), recurrence);
+        }
+    }
+
+    #[test]
+    fn recurrence_rejects_bad_rules() {
+        for rule in ["", "hourly", "monthly 0", "monthly 32", "every 0 days", "weekly", "weekly funday", "daily until tomorrow", "daily for ever"] {
+            assert!(rule.parse::<Recurrence>().is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn next_due_date_follows_the_rule() {
+        // 2024-05-03 is a Friday
+        let friday = date(2024, 5, 3);
+        assert_eq!(next_due_date(&Repeat::Daily, friday), date(2024, 5, 4));
+        assert_eq!(next_due_date(&Repeat::EveryNDays(10), friday), date(2024, 5, 13));
+        assert_eq!(next_due_date(&Repeat::Weekdays, friday), date(2024, 5, 6));
+        assert_eq!(next_due_date(&Repeat::Weekly(vec![Weekday::Fri]), friday), date(2024, 5, 10));
+        assert_eq!(next_due_date(&Repeat::Weekly(vec![Weekday::Mon, Weekday::Sat]), friday), date(2024, 5, 4));
+    }
+
+    #[test]
+    fn monthly_stays_in_the_month_while_the_day_is_ahead() {
+        assert_eq!(next_due_date(&Repeat::Monthly(20), date(2024, 1, 5)), date(2024, 1, 20));
+        assert_eq!(next_due_date(&Repeat::Monthly(20), date(2024, 1, 20)), date(2024, 2, 20));
+        assert_eq!(next_due_date(&Repeat::Monthly(5), date(2024, 12, 20)), date(2025, 1, 5));
+    }
+
+    #[test]
+    fn monthly_clamps_to_the_end_of_short_months() {
+        let rule = Repeat::Monthly(31);
+        assert_eq!(next_due_date(&rule, date(2023, 1, 31)), date(2023, 2, 28));
+        assert_eq!(next_due_date(&rule, date(2023, 2, 28)), date(2023, 3, 31));
+        assert_eq!(next_due_date(&rule, date(2024, 1, 31)), date(2024, 2, 29));
+        assert_eq!(next_due_date(&rule, date(2024, 2, 29)), date(2024, 3, 31));
+        assert_eq!(next_due_date(&rule, date(2024, 4, 10)), date(2024, 4, 30));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::transfer::{iso_due, new_list, parse_iso_due, set_done, set_due, Field, DEFAULT_LIST};
+use crate::{Error, Task, TaskList};
+
+/*** Markdown task lists ***/
+// One heading per list and one `- [ ] title (due YYYY-MM-DD)` item per task, subtasks indented
+// by two spaces under their parent
+pub const FIELDS: &[Field] = &[Field::Due, Field::Done, Field::Parent];
+
+pub fn export(lists: &[TaskList]) -> String {
+    let mut out = String::new();
+    for list in lists {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("# {}\n\n", list.title));
+        for (i, task) in list.tasks.iter().enumerate() {
+            let check = if task.done { 'x' } else { ' ' };
+            let due = iso_due(task).map(|due| format!(" (due {})", due)).unwrap_or_default();
+            out.push_str(&format!("{}- [{}] {}{}\n", "  ".repeat(crate::task_depth(list, i)), check, task.title, due));
+        }
+    }
+    out
+}
+
+// Lines that are neither headings nor task items are skipped, so a whole wiki page can be read.
+// A "(due ...)" suffix that is not an ISO date, e.g. "(due tomorrow)", is left in the title
+pub fn import(content: &str) -> Result<Vec<TaskList>, Error> {
+    let mut lists: Vec<TaskList> = vec![];
+    // indentation and id of the items the next one may be a subtask of
+    let mut parents: Vec<(usize, uuid::Uuid)> = vec![];
+    for line in content.lines() {
+        let text = line.trim_start();
+        if let Some(title) = heading(text) {
+            lists.push(new_list(title));
+            parents.clear();
+            continue;
+        }
+        let Some((done, item)) = task_item(text) else {
+            continue;
+        };
+        let mut task = Task::new(item.to_string());
+        let suffix = item.strip_suffix(')').and_then(|rest| rest.rsplit_once("(due "));
+        if let Some((title, (due, due_at))) = suffix.and_then(|(title, due)| Some((title, parse_iso_due(due)?))) {
+            task.title = title.trim_end().to_string();
+            set_due(&mut task, due, due_at);
+        }
+        if task.title.is_empty() {
+            continue;
+        }
+        set_done(&mut task, done, None);
+
+        let indent: usize = line[..line.len() - text.len()].chars().map(|c| if c == '\t' { 4 } else { 1 }).sum();
+        while parents.last().is_some_and(|&(parent_indent, _)| parent_indent >= indent) {
+            parents.pop();
+        }
+        task.parent = parents.last().map(|&(_, id)| id);
+        parents.push((indent, task.id));
+        if lists.is_empty() {
+            lists.push(new_list(DEFAULT_LIST));
+        }
+        lists.last_mut().unwrap().tasks.push(task);
+    }
+    Ok(lists)
+}
+
+// "# Title" up to "###### Title"
+fn heading(line: &str) -> Option<&str> {
+    let title = line.trim_start_matches('#');
+    let level = line.len() - title.len();
+    match (1..=6).contains(&level) && title.starts_with(' ') {
+        true => Some(title.trim()),
+        false => None,
+    }
+}
+
+// "- [ ] text", "* [x] text" or "+ [X] text", returning whether the box is ticked
+fn task_item(line: &str) -> Option<(bool, &str)> {
+    let rest = line.strip_prefix("- ").or(line.strip_prefix("* ")).or(line.strip_prefix("+ "))?;
+    let done = match rest.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    Some((done, rest[3..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_and_import_round_trip() {
+        let content = "# Work\n\n- [ ] Report (due 2024-05-03 17:30)\n  - [x] Draft (due 2024-05-02)\n    - [ ] Outline\n- [ ] Review\n\n# Home\n\n- [x] Laundry\n";
+        let lists = import(content).unwrap();
+        assert_eq!(lists.len(), 2);
+        let work = &lists[0].tasks;
+        assert_eq!(work[1].parent, Some(work[0].id));
+        assert_eq!(work[2].parent, Some(work[1].id));
+        assert_eq!(work[3].parent, None);
+        assert!(work[1].done && lists[1].tasks[0].done);
+        assert_eq!(export(&lists), content);
+    }
+
+    #[test]
+    fn unreadable_due_dates_stay_in_the_title() {
+        let lists = import("Notes\n\n- [ ] Call mom (due tomorrow)\n* [X] Taxes (due 2024-04-15)\n- [?] not a task\n").unwrap();
+        assert_eq!(lists[0].title, DEFAULT_LIST);
+        let tasks = &lists[0].tasks;
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].title, "Call mom (due tomorrow)");
+        assert!(tasks[0].date_string.is_empty());
+        assert_eq!(tasks[1].title, "Taxes");
+        assert_eq!(iso_due(&tasks[1]).as_deref(), Some("2024-04-15"));
+    }
+}
@@ -0,0 +1,235 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use uuid::Uuid;
+
+use crate::transfer::{iso_due, list_index, parse_iso_due, set_done, set_due, Field, ImportOptions, Imported, DEFAULT_LIST};
+use crate::{Error, Priority, Task, TaskList};
+
+/*** CSV ***/
+// Export writes every task field, import reads the columns below and ignores the rest
+const HEADERS: [&str; 15] = [
+    "id", "list", "title", "due", "done", "completed_at", "priority", "tags", "notes",
+    "checklist", "recurrence", "blocked_by", "parent", "created_at", "updated_at",
+];
+const IMPORTED_COLUMNS: [&str; 8] = ["id", "list", "title", "due", "done", "priority", "tags", "notes"];
+
+fn priority_name(priority: Priority) -> &'static str {
+    match priority {
+        Priority::None => "",
+        Priority::Low => "low",
+        Priority::Medium => "medium",
+        Priority::High => "high",
+        Priority::Urgent => "urgent",
+    }
+}
+
+pub fn export(lists: &[TaskList]) -> Result<String, Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(HEADERS)?;
+    for list in lists {
+        for task in list.tasks.iter() {
+            let checklist: Vec<String> = task.checklist.iter().map(|item| format!("[{}] {}", if item.done { 'x' } else { ' ' }, item.text)).collect();
+            let blocked_by: Vec<String> = task.blocked_by.iter().map(Uuid::to_string).collect();
+            writer.write_record([
+                task.id.to_string(),
+                list.title.clone(),
+                task.title.clone(),
+                iso_due(task).unwrap_or_default(),
+                task.done.to_string(),
+                task.completed_at.map(|time| time.to_rfc3339()).unwrap_or_default(),
+                priority_name(task.priority).to_string(),
+                task.tags.iter().cloned().collect::<Vec<_>>().join(" "),
+                task.notes.clone(),
+                checklist.join("; "),
+                task.recurrence.as_ref().map(|recurrence| recurrence.to_string()).unwrap_or_default(),
+                blocked_by.join(" "),
+                task.parent.map(|parent| parent.to_string()).unwrap_or_default(),
+                task.created_at.to_rfc3339(),
+                task.updated_at.to_rfc3339(),
+            ])?;
+        }
+    }
+    let bytes = writer.into_inner().map_err(|err| err.into_error())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// YYYY-MM-DD with an optional HH:MM unless the user chose a format, which may or may not have a time
+fn parse_date(value: &str, format: Option<&str>) -> Option<(NaiveDate, Option<DateTime<Local>>)> {
+    let Some(format) = format else {
+        return parse_iso_due(value);
+    };
+    if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+        let due_at = Local.from_local_datetime(&naive).earliest()?;
+        return Some((due_at.date_naive(), Some(due_at)));
+    }
+    NaiveDate::parse_from_str(value, format).ok().map(|date| (date, None))
+}
+
+fn parse_done(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "" | "false" | "no" | "n" | "0" | "open" | "todo" => Some(false),
+        "true" | "yes" | "y" | "1" | "x" | "done" | "completed" => Some(true),
+        _ => None,
+    }
+}
+
+fn parse_priority(value: &str) -> Option<Priority> {
+    match value.to_lowercase().as_str() {
+        "" | "none" => Some(Priority::None),
+        "low" => Some(Priority::Low),
+        "medium" => Some(Priority::Medium),
+        "high" => Some(Priority::High),
+        "urgent" => Some(Priority::Urgent),
+        _ => None,
+    }
+}
+
+// Reads one row into its list title and task
+fn read_row(row: &csv::StringRecord, columns: &[(&str, usize)], date_format: Option<&str>) -> Result<(String, Task), String> {
+    let mut list = DEFAULT_LIST.to_string();
+    let mut task = Task::new(String::new());
+    for &(field, column) in columns {
+        let value = row.get(column).unwrap_or_default().trim();
+        match field {
+            "id" if !value.is_empty() => task.id = Uuid::parse_str(value).map_err(|_| format!("\"{}\" is not a task id", value))?,
+            "list" if !value.is_empty() => list = value.to_string(),
+            "title" => task.title = value.to_string(),
+            "due" if !value.is_empty() => {
+                let (due, due_at) = parse_date(value, date_format).ok_or_else(|| format!("cannot read the due date \"{}\"", value))?;
+                set_due(&mut task, due, due_at);
+            }
+            "done" => set_done(&mut task, parse_done(value).ok_or_else(|| format!("cannot tell whether \"{}\" means done", value))?, None),
+            "priority" => task.priority = parse_priority(value).ok_or_else(|| format!("unknown priority \"{}\"", value))?,
+            "tags" => task.tags = crate::parse_tags(value),
+            "notes" => task.notes = value.to_string(),
+            _ => {}
+        }
+    }
+    match task.title.is_empty() {
+        true => Err("the title is empty".to_string()),
+        false => Ok((list, task)),
+    }
+}
+
+// Columns are found by header, case-insensitively, under the field's own name unless mapped with
+// --columns. Rows that cannot be read are collected for the user to review instead of failing
+pub fn import(content: &str, options: &ImportOptions) -> Result<Imported, Error> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let find = |header: &str| headers.iter().position(|candidate| candidate.trim().eq_ignore_ascii_case(header));
+
+    for (field, _) in options.columns.iter() {
+        if !IMPORTED_COLUMNS.contains(&field.as_str()) {
+            return Err(Error::ImportError(format!("cannot map a column to \"{}\", expected one of {}", field, IMPORTED_COLUMNS.join(", "))));
+        }
+    }
+    let mut columns = vec![];
+    for field in IMPORTED_COLUMNS {
+        let header = options.columns.iter().find(|(mapped, _)| mapped == field).map_or(field, |(_, header)| header.as_str());
+        match find(header) {
+            Some(column) => columns.push((field, column)),
+            None if header != field => return Err(Error::ImportError(format!("there is no column \"{}\" to read {} from", header, field))),
+            None => {}
+        }
+    }
+    if !columns.iter().any(|&(field, _)| field == "title") {
+        return Err(Error::ImportError("there is no title column, map one with --columns title=<header>".to_string()));
+    }
+    let mapped = |field| columns.iter().any(|&(mapped, _)| mapped == field);
+    let fields: Vec<Field> = [
+        ("due", Field::Due),
+        ("done", Field::Done),
+        ("notes", Field::Notes),
+        ("priority", Field::Priority),
+        ("tags", Field::Tags),
+    ].into_iter().filter(|&(name, _)| mapped(name)).map(|(_, field)| field).collect();
+
+    let mut lists: Vec<TaskList> = vec![];
+    let mut rejected = vec![];
+    for (i, row) in reader.records().enumerate() {
+        // the header is row 1
+        let number = i + 2;
+        let read = row.map_err(|err| err.to_string()).and_then(|row| {
+            read_row(&row, &columns, options.date_format.as_deref()).map_err(|err| format!("({}) {}", row.iter().collect::<Vec<_>>().join(", "), err))
+        });
+        match read {
+            Ok((title, task)) => {
+                let index = list_index(&mut lists, &title);
+                lists[index].tasks.push(task);
+            }
+            Err(err) => rejected.push(format!("row {}: {}", number, err)),
+        }
+    }
+    Ok(Imported { lists, fields, rejected })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transfer::new_list;
+
+    fn options(columns: &[(&str, &str)], date_format: Option<&str>) -> ImportOptions {
+        ImportOptions {
+            columns: columns.iter().map(|(field, header)| (field.to_string(), header.to_string())).collect(),
+            date_format: date_format.map(str::to_string),
+        }
+    }
+
+    fn import_err(content: &str, options: &ImportOptions) -> String {
+        match import(content, options) {
+            Ok(_) => panic!("the import was expected to fail"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn export_and_import_round_trip() {
+        let mut list = new_list("Work");
+        let mut task = Task::new("Report, final".to_string());
+        set_due(&mut task, NaiveDate::from_ymd_opt(2024, 5, 3).unwrap(), None);
+        task.priority = Priority::High;
+        task.tags = crate::parse_tags("q2 writing");
+        task.notes = "two\nlines".to_string();
+        list.tasks.push(task.clone());
+
+        let imported = import(&export(&[list]).unwrap(), &ImportOptions::default()).unwrap();
+        assert!(imported.rejected.is_empty());
+        assert_eq!(imported.fields, [Field::Due, Field::Done, Field::Notes, Field::Priority, Field::Tags]);
+        let read = &imported.lists[0].tasks[0];
+        assert_eq!(imported.lists[0].title, "Work");
+        assert_eq!((read.id, &read.title, read.due, read.priority), (task.id, &task.title, task.due, task.priority));
+        assert_eq!((&read.tags, &read.notes), (&task.tags, &task.notes));
+    }
+
+    #[test]
+    fn mapped_columns_and_date_formats() {
+        let content = "Task,Deadline,Project,Status,Ignored\nPay rent,03/05/2024,Home,done,x\nCall bank,04/05/2024,,open,y\n";
+        let imported = import(content, &options(&[("title", "task"), ("due", "Deadline"), ("list", "PROJECT"), ("done", "Status")], Some("%d/%m/%Y"))).unwrap();
+        assert_eq!(imported.fields, [Field::Due, Field::Done]);
+        let titles: Vec<&str> = imported.lists.iter().map(|list| list.title.as_str()).collect();
+        assert_eq!(titles, ["Home", DEFAULT_LIST]);
+        let rent = &imported.lists[0].tasks[0];
+        assert!(rent.done);
+        assert_eq!(rent.due, NaiveDate::from_ymd_opt(2024, 5, 3).unwrap());
+        assert_eq!(imported.lists[1].tasks[0].due, NaiveDate::from_ymd_opt(2024, 5, 4).unwrap());
+    }
+
+    #[test]
+    fn unreadable_rows_are_rejected_and_the_rest_kept() {
+        let content = "title,due,priority\nReport,2024-05-03,high\n,2024-05-03,\nReview,soon,\nShip,,asap\nTest,2024-05-06 09:30,\n";
+        let imported = import(content, &ImportOptions::default()).unwrap();
+        let titles: Vec<&str> = imported.lists[0].tasks.iter().map(|task| task.title.as_str()).collect();
+        assert_eq!(titles, ["Report", "Test"]);
+        assert!(imported.lists[0].tasks[1].due_at.is_some());
+        assert_eq!(imported.rejected.len(), 3);
+        assert!(imported.rejected[0].starts_with("row 3: ") && imported.rejected[0].ends_with("the title is empty"));
+        assert!(imported.rejected[1].contains("cannot read the due date \"soon\""));
+        assert!(imported.rejected[2].contains("unknown priority \"asap\""));
+    }
+
+    #[test]
+    fn missing_or_bad_column_mappings_fail() {
+        assert!(import_err("name,due\nReport,\n", &ImportOptions::default()).contains("there is no title column"));
+        assert!(import_err("name\nReport\n", &options(&[("title", "Task")], None)).contains("there is no column \"Task\""));
+        assert!(import_err("name\nReport\n", &options(&[("owner", "name")], None)).contains("cannot map a column to \"owner\""));
+    }
+}
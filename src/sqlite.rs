@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde_json::Value;
+
+use crate::db::{backup_with, parent_dir, upgrade, DbFile, DB_VERSION};
+use crate::storage::Storage;
+use crate::Error;
+
+/*** SQLite backend ***/
+// Lists and tasks are rows holding their JSON, so every field survives without a column per field
+// and the regular migrations apply to them
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS board (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        version INTEGER NOT NULL,
+        state TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS lists (
+        id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS tasks (
+        list_id TEXT NOT NULL,
+        id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (list_id, id)
+    );
+";
+
+/*
+* A board stored in an SQLite database
+* path: PathBuf - The database file
+* conn: Connection - Open connection to the database file
+* board: Option<(u64, String)> - Stored version and board state, None while the database is empty
+* lists: HashMap<String, (usize, String)> - Stored position and JSON of every list by id
+* tasks: HashMap<(String, String), (usize, String)> - Stored position and JSON of every task by list and task id
+* data_version: i64 - SQLite's counter of commits made through other connections, as of the last load
+*/
+pub struct SqliteStorage {
+    path: PathBuf,
+    conn: Connection,
+    data_version: i64,
+    board: Option<(u64, String)>,
+    lists: HashMap<String, (usize, String)>,
+    tasks: HashMap<(String, String), (usize, String)>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> Result<SqliteStorage, Error> {
+        fs::create_dir_all(parent_dir(path))?;
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(SqliteStorage { path: path.to_path_buf(), conn, data_version: 0, board: None, lists: HashMap::new(), tasks: HashMap::new() })
+    }
+
+    fn data_version(&self) -> Result<i64, Error> {
+        Ok(self.conn.query_row("PRAGMA data_version", [], |row| row.get(0))?)
+    }
+}
+
+// How many lists and tasks a database holds, read without changing it, e.g. to describe a backup
+pub fn count_rows(path: &Path) -> Result<(usize, usize), Error> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let count = |table: &str| conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get::<_, i64>(0));
+    Ok((count("lists")? as usize, count("tasks")? as usize))
+}
+
+impl Storage for SqliteStorage {
+    fn load(&mut self) -> Result<DbFile, Error> {
+        self.data_version = self.data_version()?;
+        self.board = self.conn
+            .query_row("SELECT version, state FROM board WHERE id = 0", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        self.lists = self.conn
+            .prepare("SELECT id, position, data FROM lists")?
+            .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+            .collect::<Result<_, _>>()?;
+        self.tasks = self.conn
+            .prepare("SELECT list_id, id, position, data FROM tasks")?
+            .query_map([], |row| Ok(((row.get(0)?, row.get(1)?), (row.get(2)?, row.get(3)?))))?
+            .collect::<Result<_, _>>()?;
+        let Some((version, state)) = &self.board else {
+            return Ok(DbFile { version: DB_VERSION, ..DbFile::default() });
+        };
+
+        // reassemble the envelope the JSON backend would have read
+        let mut lists: Vec<(&usize, Value)> = vec![];
+        for (id, (position, data)) in &self.lists {
+            let mut list: Value = serde_json::from_str(data)?;
+            let mut tasks: Vec<(&usize, Value)> = vec![];
+            for ((list_id, _), (position, data)) in &self.tasks {
+                if list_id == id {
+                    tasks.push((position, serde_json::from_str(data)?));
+                }
+            }
+            tasks.sort_by_key(|task| task.0);
+            list["tasks"] = Value::Array(tasks.into_iter().map(|task| task.1).collect());
+            lists.push((position, list));
+        }
+        lists.sort_by_key(|list| list.0);
+        upgrade(serde_json::json!({
+            "version": version,
+            "board": serde_json::from_str::<Value>(state)?,
+            "lists": lists.into_iter().map(|list| list.1).collect::<Vec<_>>(),
+        }))
+    }
+
+    // Only rows whose position or contents changed since the last load or save are written. Backups
+    // are taken with VACUUM INTO, which copies a consistent state even while another instance writes
+    fn save(&mut self, db: &DbFile) -> Result<(), Error> {
+        if self.board.is_some() {
+            backup_with(&self.path, |backup_path| {
+                self.conn.execute("VACUUM INTO ?1", params![backup_path.to_string_lossy()])?;
+                Ok(())
+            })?;
+        }
+        let board = (DB_VERSION, serde_json::to_string(&db.board)?);
+        let mut lists = HashMap::new();
+        let mut tasks = HashMap::new();
+        for (position, list) in db.lists.iter().enumerate() {
+            let list_id = list.id.to_string();
+            for (position, task) in list.tasks.iter().enumerate() {
+                tasks.insert((list_id.clone(), task.id.to_string()), (position, serde_json::to_string(task)?));
+            }
+            let mut data = serde_json::to_value(list)?;
+            if let Value::Object(fields) = &mut data {
+                fields.remove("tasks");
+            }
+            lists.insert(list_id, (position, data.to_string()));
+        }
+
+        let tx = self.conn.transaction()?;
+        if self.board.as_ref() != Some(&board) {
+            tx.execute("INSERT OR REPLACE INTO board (id, version, state) VALUES (0, ?1, ?2)", params![board.0, board.1])?;
+        }
+        for (id, row) in &lists {
+            if self.lists.get(id) != Some(row) {
+                tx.execute("INSERT OR REPLACE INTO lists (id, position, data) VALUES (?1, ?2, ?3)", params![id, row.0, row.1])?;
+            }
+        }
+        for id in self.lists.keys().filter(|id| !lists.contains_key(*id)) {
+            tx.execute("DELETE FROM lists WHERE id = ?1", params![id])?;
+        }
+        for ((list_id, id), row) in &tasks {
+            if self.tasks.get(&(list_id.clone(), id.clone())) != Some(row) {
+                tx.execute(
+                    "INSERT OR REPLACE INTO tasks (list_id, id, position, data) VALUES (?1, ?2, ?3, ?4)",
+                    params![list_id, id, row.0, row.1],
+                )?;
+            }
+        }
+        for (list_id, id) in self.tasks.keys().filter(|key| !tasks.contains_key(*key)) {
+            tx.execute("DELETE FROM tasks WHERE list_id = ?1 AND id = ?2", params![list_id, id])?;
+        }
+        tx.commit()?;
+
+        self.board = Some(board);
+        self.lists = lists;
+        self.tasks = tasks;
+        Ok(())
+    }
+
+    // Commits made through this connection leave data_version alone
+    fn changed_externally(&mut self) -> Result<bool, Error> {
+        Ok(self.data_version()? != self.data_version)
+    }
+
+    fn exists(&self) -> bool {
+        self.path.exists()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+    use crate::transfer::new_list;
+    use crate::Task;
+
+    #[test]
+    fn saves_load_back_and_are_backed_up() {
+        let dir = temp_dir();
+        let path = dir.join("board.sqlite");
+        let mut list = new_list("Work");
+        list.tasks = vec![Task::new("Report".to_string()), Task::new("Review".to_string())];
+        let mut db = DbFile { version: DB_VERSION, lists: vec![list], ..DbFile::default() };
+
+        let mut storage = SqliteStorage::open(&path).unwrap();
+        storage.load().unwrap();
+        storage.save(&db).unwrap();
+        db.lists[0].tasks.remove(0);
+        storage.save(&db).unwrap();
+
+        let loaded = SqliteStorage::open(&path).unwrap().load().unwrap();
+        assert_eq!(serde_json::to_value(&loaded.lists).unwrap(), serde_json::to_value(&db.lists).unwrap());
+        // the first save had nothing to back up, the second kept the state before it
+        let backups: Vec<PathBuf> = fs::read_dir(dir.path()).unwrap().flatten().map(|entry| entry.path())
+            .filter(|path| path.to_string_lossy().contains(".backup-"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(count_rows(&backups[0]).unwrap(), (1, 2));
+    }
+}
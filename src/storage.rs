@@ -0,0 +1,150 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::db::{board_path, read_db, sibling_path, write_db, DbFile, MAIN_BOARD};
+use crate::sqlite::SqliteStorage;
+use crate::Error;
+
+/*** Storage backends ***/
+// Board files with one of these extensions are SQLite databases, anything else is JSON
+pub const SQLITE_EXTENSIONS: [&str; 3] = ["sqlite", "sqlite3", "db"];
+
+pub trait Storage {
+    // Reads the whole board, starting an empty one if nothing is stored yet
+    fn load(&mut self) -> Result<DbFile, Error>;
+    // Persists the board, taking a rotating backup first for `restore`. Backends may write only
+    // what changed since the last load or save
+    fn save(&mut self, db: &DbFile) -> Result<(), Error>;
+    // Whether something else wrote to the board since this storage last loaded or saved it
+    fn changed_externally(&mut self) -> Result<bool, Error>;
+    // Whether the board's file is still there, another instance may have deleted or renamed it
+    fn exists(&self) -> bool;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+impl Backend {
+    pub fn of(path: &Path) -> Backend {
+        match path.extension() {
+            Some(ext) if SQLITE_EXTENSIONS.iter().any(|sqlite| ext == *sqlite) => Backend::Sqlite,
+            _ => Backend::Json,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Backend::Json => "json",
+            Backend::Sqlite => "sqlite",
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "json" => Ok(Backend::Json),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(format!("unknown storage backend: {} (expected json or sqlite)", input)),
+        }
+    }
+}
+
+/*
+* The whole board rewritten as one pretty-printed file, with rotating backups
+* path: PathBuf - The board file
+* stamp: Option<(SystemTime, u64)> - Modification time and size of the file as last loaded or saved
+*/
+pub struct JsonStorage {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+}
+
+impl JsonStorage {
+    fn current_stamp(&self) -> Option<(SystemTime, u64)> {
+        let meta = fs::metadata(&self.path).ok()?;
+        Some((meta.modified().ok()?, meta.len()))
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load(&mut self) -> Result<DbFile, Error> {
+        let db = read_db(&self.path)?;
+        self.stamp = self.current_stamp();
+        Ok(db)
+    }
+
+    fn save(&mut self, db: &DbFile) -> Result<(), Error> {
+        write_db(&self.path, db)?;
+        self.stamp = self.current_stamp();
+        Ok(())
+    }
+
+    fn changed_externally(&mut self) -> Result<bool, Error> {
+        Ok(self.current_stamp() != self.stamp)
+    }
+
+    fn exists(&self) -> bool {
+        self.path.exists()
+    }
+}
+
+pub fn open_storage(path: &Path) -> Result<Box<dyn Storage>, Error> {
+    Ok(match Backend::of(path) {
+        Backend::Json => Box::new(JsonStorage { path: path.to_path_buf(), stamp: None }),
+        Backend::Sqlite => Box::new(SqliteStorage::open(path)?),
+    })
+}
+
+/*** Moving a board between backends ***/
+// `migrate-storage <backend>` copies the board into a file of that backend next to the current one,
+// checks that it reads back identically and only then moves the old file aside
+pub fn migrate_storage_command(db_path: &Path, board_name: &str, backend: Backend) -> Result<(), Error> {
+    let source_path = board_path(db_path, board_name);
+    if Backend::of(&source_path) == backend {
+        println!("{} is already stored as {}", source_path.display(), backend.extension());
+        return Ok(());
+    }
+    if !source_path.exists() {
+        println!("Nothing to migrate, {} does not exist", source_path.display());
+        return Ok(());
+    }
+    let target_path = source_path.with_extension(backend.extension());
+    if target_path.exists() {
+        return Err(Error::TargetExists(target_path));
+    }
+
+    let db = open_storage(&source_path)?.load()?;
+    let copied = open_storage(&target_path).and_then(|mut target| {
+        target.save(&db)?;
+        open_storage(&target_path)?.load()
+    });
+    let lossless = match &copied {
+        Ok(copied) => serde_json::to_value(copied)? == serde_json::to_value(&db)?,
+        Err(_) => false,
+    };
+    if !lossless {
+        let _ = fs::remove_file(&target_path);
+        return match copied {
+            Err(err) => Err(err),
+            Ok(_) => Err(Error::MigrationMismatch(source_path)),
+        };
+    }
+
+    let old_path = sibling_path(&source_path, ".migrated");
+    fs::rename(&source_path, &old_path)?;
+    let tasks: usize = db.lists.iter().map(|list| list.tasks.len()).sum();
+    println!("Moved {} lists and {} tasks to {}", db.lists.len(), tasks, target_path.display());
+    println!("The old file was kept as {}", old_path.display());
+    if board_name == MAIN_BOARD {
+        println!("If you set --db or $TASKBOARD_DB, point it at the new file");
+    }
+    Ok(())
+}
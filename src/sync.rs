@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use uuid::Uuid;
+
+use crate::activity::{append_events, read_events, Event};
+use crate::db::{sibling_path, DbFile};
+use crate::history::{read_history, write_history, History};
+use crate::storage::Storage;
+use crate::{fix_selection, Error, Task, TaskBoard, TaskList};
+
+/*** Lock file ***/
+// Whether the process that wrote a lock file is still running, None without /proc to tell
+fn process_alive(pid: u32) -> Option<bool> {
+    cfg!(target_os = "linux").then(|| Path::new("/proc").join(pid.to_string()).exists())
+}
+
+// A lock file with no process id yet may be one another instance has only just created
+fn just_created(path: &Path) -> bool {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+        .and_then(|time| time.elapsed().ok())
+        .is_some_and(|age| age < Duration::from_secs(1))
+}
+
+/*
+* Marks a board as open in this process
+* path: PathBuf - The lock file next to the board file
+* owned: bool - Whether this process created the lock and removes it when done
+* other: Option<u32> - Process id of another instance that already had the board open
+*/
+struct Lock {
+    path: PathBuf,
+    owned: bool,
+    other: Option<u32>,
+}
+
+impl Lock {
+    // Creating the file only if it does not exist yet keeps two instances from both taking the
+    // lock. A lock left behind by a crashed instance, or one whose owner cannot be checked, is
+    // removed and taken over
+    fn acquire(board_path: &Path) -> Lock {
+        let path = sibling_path(board_path, ".lock");
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let _ = write!(file, "{}", std::process::id());
+                    return Lock { path, owned: true, other: None };
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    let holder = fs::read_to_string(&path).ok().and_then(|pid| pid.trim().parse::<u32>().ok());
+                    match holder {
+                        Some(pid) if pid != std::process::id() && process_alive(pid) == Some(true) => {
+                            return Lock { path, owned: false, other: Some(pid) };
+                        }
+                        None if just_created(&path) => break,
+                        _ => {
+                            let _ = fs::remove_file(&path);
+                        }
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        Lock { path, owned: false, other: None }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if self.owned {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/*** Keeping the board and its file in step ***/
+/*
+* A board file open in this instance
+* storage: Box<dyn Storage> - Backend the board is read from and written to
+* base: DbFile - The board as last read or written, the common ancestor when merging outside changes
+* lock: Lock - This instance's claim on the board file
+* history_path: PathBuf - Where the undo history is kept, next to the board file
+* log_path: PathBuf - The activity log, next to the board file
+* missing: bool - Whether the board file was found gone since the last save
+*/
+pub struct OpenBoard {
+    storage: Box<dyn Storage>,
+    base: DbFile,
+    lock: Lock,
+    history_path: PathBuf,
+    log_path: PathBuf,
+    missing: bool,
+}
+
+impl OpenBoard {
+    pub fn new(path: &Path, storage: Box<dyn Storage>, db: &DbFile) -> OpenBoard {
+        OpenBoard {
+            storage,
+            base: db.clone(),
+            lock: Lock::acquire(path),
+            history_path: sibling_path(path, ".undo"),
+            log_path: sibling_path(path, ".log"),
+            missing: false,
+        }
+    }
+
+    // Undo history left by the last session, empty if there is none or it cannot be read
+    pub fn history(&self) -> History {
+        read_history(&self.history_path)
+    }
+
+    // Everything logged for this board so far, oldest first
+    pub fn events(&self) -> Vec<Event> {
+        read_events(&self.log_path)
+    }
+
+    // Status note when another instance had the board open first
+    pub fn shared_note(&self) -> Option<String> {
+        self.lock.other.map(|pid| format!("Board is also open in another instance (pid {}), changes from both are merged", pid))
+    }
+
+    // Pulls in changes another instance wrote since the last load or save. Returns a status note
+    // when something was merged, or once when the file went missing
+    pub fn sync(&mut self, taskboard: &mut TaskBoard) -> Result<Option<String>, Error> {
+        // a deleted or renamed file would load as an empty board and merge away every list, so
+        // the board stays as it is here and the next save writes it back
+        if !self.storage.exists() {
+            let first = !std::mem::replace(&mut self.missing, true);
+            return Ok(first.then(|| "The board file was deleted or renamed elsewhere, this copy is kept and saved again".to_string()));
+        }
+        if !self.storage.changed_externally()? {
+            return Ok(None);
+        }
+        let theirs = self.storage.load()?;
+        let merge = merge(&self.base.lists, &taskboard.lists, &theirs.lists);
+        taskboard.lists = merge.lists;
+        taskboard.num_lists = taskboard.lists.len();
+        taskboard.active_list = taskboard.active_list.clamp(1, taskboard.num_lists.max(1));
+        for list in taskboard.lists.iter_mut() {
+            fix_selection(list, &taskboard.tag_filter);
+        }
+        taskboard.history.resync(&taskboard.lists);
+        self.base = theirs;
+        let note = match merge.conflicts {
+            0 => "Reloaded changes made in another instance".to_string(),
+            n => format!("Merged changes made in another instance, {} edited in both kept the newest edit", n),
+        };
+        Ok(Some(note))
+    }
+
+    // Merges outside changes first so saving never overwrites them
+    pub fn save(&mut self, taskboard: &mut TaskBoard) -> Result<Option<String>, Error> {
+        let note = self.sync(taskboard)?;
+        let db = DbFile::from(&*taskboard);
+        self.storage.save(&db)?;
+        self.base = db;
+        self.missing = false;
+        write_history(&self.history_path, &taskboard.history)?;
+        append_events(&self.log_path, &taskboard.activity)?;
+        taskboard.activity.clear();
+        Ok(note)
+    }
+}
+
+/*** Three-way merge ***/
+/*
+* The board after merging two edited copies of it
+* lists: Vec<TaskList> - Merged lists in our order, lists only they have come last
+* conflicts: usize - Lists and tasks edited on both sides
+*/
+struct Merge {
+    lists: Vec<TaskList>,
+    conflicts: usize,
+}
+
+// Which copy of an item survives: a side that left the item alone gives way to the side that
+// changed it, an item edited on both sides keeps the newest edit and an edit beats a deletion.
+// Returns the survivor and whether both sides changed it
+fn resolve<'a, T: PartialEq>(
+    base: Option<&T>,
+    ours: Option<&'a T>,
+    theirs: Option<&'a T>,
+    updated_at: fn(&T) -> DateTime<Local>,
+) -> (Option<&'a T>, bool) {
+    if ours == theirs || theirs == base {
+        return (ours, false);
+    }
+    if ours == base {
+        return (theirs, false);
+    }
+    match (ours, theirs) {
+        (Some(ours), Some(theirs)) if updated_at(theirs) > updated_at(ours) => (Some(theirs), true),
+        (Some(ours), _) => (Some(ours), true),
+        (None, theirs) => (theirs, true),
+    }
+}
+
+// A list without its tasks and view state, to tell renames and such apart from task changes
+fn list_header(list: &TaskList) -> TaskList {
+    TaskList { tasks: vec![], selected: 0, updated_at: list.created_at, ..list.clone() }
+}
+
+fn merge(base: &[TaskList], ours: &[TaskList], theirs: &[TaskList]) -> Merge {
+    let find = |lists: &[TaskList], id: Uuid| lists.iter().find(|list| list.id == id).cloned();
+    let mut conflicts = 0;
+
+    let mut list_ids: Vec<Uuid> = ours.iter().map(|list| list.id).collect();
+    list_ids.extend(theirs.iter().map(|list| list.id).filter(|id| !ours.iter().any(|list| list.id == *id)));
+
+    let mut lists = vec![];
+    for id in list_ids {
+        let (base_list, our_list, their_list) = (find(base, id), find(ours, id), find(theirs, id));
+        let list = match (&our_list, &their_list) {
+            (Some(our_list), Some(their_list)) => {
+                let (base_header, our_header, their_header) = (base_list.as_ref().map(list_header), list_header(our_list), list_header(their_list));
+                let take_theirs = if our_header == their_header || base_header.as_ref() == Some(&their_header) {
+                    false
+                } else if base_header.as_ref() == Some(&our_header) {
+                    true
+                } else {
+                    conflicts += 1;
+                    their_list.updated_at > our_list.updated_at
+                };
+                let source = if take_theirs { their_list } else { our_list };
+                TaskList { updated_at: our_list.updated_at.max(their_list.updated_at), ..source.clone() }
+            }
+            // a list deleted on one side stays deleted unless the other side changed it
+            _ => {
+                let (list, conflict) = resolve(base_list.as_ref(), our_list.as_ref(), their_list.as_ref(), |list| list.updated_at);
+                conflicts += conflict as usize;
+                match list {
+                    Some(list) => list.clone(),
+                    None => continue,
+                }
+            }
+        };
+        lists.push(TaskList { tasks: vec![], ..list });
+    }
+
+    // tasks are merged across the whole board, so a task moved to another list is not duplicated
+    let index = |lists: &[TaskList]| -> HashMap<Uuid, (Uuid, Task)> {
+        lists.iter().flat_map(|list| list.tasks.iter().map(|task| (task.id, (list.id, task.clone())))).collect()
+    };
+    let (base_tasks, our_tasks, their_tasks) = (index(base), index(ours), index(theirs));
+    let mut task_ids: Vec<Uuid> = ours.iter().flat_map(|list| list.tasks.iter().map(|task| task.id)).collect();
+    task_ids.extend(theirs.iter().flat_map(|list| list.tasks.iter().map(|task| task.id)).filter(|id| !our_tasks.contains_key(id)));
+    for id in task_ids {
+        let (task, conflict) = resolve(base_tasks.get(&id), our_tasks.get(&id), their_tasks.get(&id), |(_, task)| task.updated_at);
+        conflicts += conflict as usize;
+        // tasks whose list was deleted go with it
+        if let Some((list_id, task)) = task {
+            if let Some(list) = lists.iter_mut().find(|list| list.id == *list_id) {
+                list.tasks.push(task.clone());
+            }
+        }
+    }
+
+    // keep our selection on the same task
+    for list in lists.iter_mut() {
+        let selected_id = ours.iter()
+            .find(|our_list| our_list.id == list.id)
+            .and_then(|our_list| our_list.tasks.get(our_list.selected))
+            .map(|task| task.id);
+        list.selected = selected_id.and_then(|id| list.tasks.iter().position(|task| task.id == id)).unwrap_or(0);
+    }
+    Merge { lists, conflicts }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::History;
+    use crate::storage::open_storage;
+    use crate::testing::{list, temp_dir, titles};
+
+    fn owned(titles: &[(&str, &[&str])]) -> Vec<(String, Vec<String>)> {
+        titles.iter().map(|(list, tasks)| (list.to_string(), tasks.iter().map(|task| task.to_string()).collect())).collect()
+    }
+
+    fn edit(task: &mut Task, title: &str) {
+        task.title = title.to_string();
+        task.updated_at = Local::now();
+    }
+
+    #[test]
+    fn changes_from_both_sides_are_kept() {
+        let base = vec![list("Work", &["Report", "Review", "Deck"])];
+        let (mut ours, mut theirs) = (base.clone(), base.clone());
+        edit(&mut ours[0].tasks[0], "Final report");
+        ours[0].tasks.push(Task::new("Mine".to_string()));
+        theirs[0].tasks.remove(2);
+        theirs[0].tasks.push(Task::new("Theirs".to_string()));
+        theirs.push(list("Home", &["Laundry"]));
+
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged.conflicts, 0);
+        assert_eq!(titles(&merged.lists), owned(&[("Work", &["Final report", "Review", "Mine", "Theirs"]), ("Home", &["Laundry"])]));
+    }
+
+    #[test]
+    fn edits_on_both_sides_keep_the_newest() {
+        let base = vec![list("Work", &["Report"])];
+        let (mut ours, mut theirs) = (base.clone(), base.clone());
+        edit(&mut ours[0].tasks[0], "Ours");
+        edit(&mut theirs[0].tasks[0], "Theirs");
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(titles(&merged.lists), owned(&[("Work", &["Theirs"])]));
+    }
+
+    #[test]
+    fn an_edit_beats_a_deletion() {
+        let base = vec![list("Work", &["Report", "Review"])];
+        let (mut ours, mut theirs) = (base.clone(), base.clone());
+        edit(&mut ours[0].tasks[0], "Final report");
+        theirs[0].tasks.clear();
+        let merged = merge(&base, &ours, &theirs);
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(titles(&merged.lists), owned(&[("Work", &["Final report"])]));
+
+        // a list deleted on one side and left alone on the other goes with its tasks
+        let base = vec![list("Work", &["Report"]), list("Home", &["Laundry"])];
+        let (ours, mut theirs) = (base.clone(), base.clone());
+        theirs.remove(1);
+        assert_eq!(titles(&merge(&base, &ours, &theirs).lists), owned(&[("Work", &["Report"])]));
+    }
+
+    #[test]
+    fn a_task_moved_to_another_list_is_not_duplicated() {
+        let base = vec![list("Work", &["Report"]), list("Home", &[])];
+        let (ours, mut theirs) = (base.clone(), base.clone());
+        let mut task = theirs[0].tasks.remove(0);
+        task.updated_at = Local::now();
+        theirs[1].tasks.push(task);
+        assert_eq!(titles(&merge(&base, &ours, &theirs).lists), owned(&[("Work", &[]), ("Home", &["Report"])]));
+    }
+
+    #[test]
+    fn a_missing_board_file_keeps_the_open_board() {
+        let dir = temp_dir();
+        let path = dir.join("board.json");
+        let db = DbFile { version: crate::db::DB_VERSION, lists: vec![list("Work", &["Report"])], ..DbFile::default() };
+        let mut storage = open_storage(&path).unwrap();
+        storage.save(&db).unwrap();
+        let mut board = OpenBoard::new(&path, storage, &db);
+        let mut taskboard = crate::new_taskboard(db, "main".to_string(), String::new(), History::default());
+
+        fs::remove_file(&path).unwrap();
+        assert!(board.sync(&mut taskboard).unwrap().is_some());
+        assert!(board.sync(&mut taskboard).unwrap().is_none());
+        assert_eq!(titles(&taskboard.lists), owned(&[("Work", &["Report"])]));
+        assert!(!path.exists());
+
+        board.save(&mut taskboard).unwrap();
+        let saved = open_storage(&path).unwrap().load().unwrap();
+        assert_eq!(titles(&saved.lists), owned(&[("Work", &["Report"])]));
+    }
+
+    #[test]
+    fn a_lock_is_taken_once_and_stale_ones_are_taken_over() {
+        let dir = temp_dir();
+        let board_path = dir.join("board.json");
+        let lock_path = sibling_path(&board_path, ".lock");
+        let lock = Lock::acquire(&board_path);
+        assert!(lock.owned);
+        assert_eq!(fs::read_to_string(&lock_path).unwrap(), std::process::id().to_string());
+        drop(lock);
+        assert!(!lock_path.exists());
+
+        // pid 1 is always running, so the board is shared with it where that can be checked
+        fs::write(&lock_path, "1").unwrap();
+        let lock = Lock::acquire(&board_path);
+        assert_eq!(lock.owned, process_alive(1).is_none());
+        assert_eq!(lock.other, process_alive(1).map(|_| 1));
+        drop(lock);
+
+        fs::write(&lock_path, u32::MAX.to_string()).unwrap();
+        let lock = Lock::acquire(&board_path);
+        assert!(lock.owned && lock.other.is_none());
+        assert_eq!(fs::read_to_string(&lock_path).unwrap(), std::process::id().to_string());
+    }
+}
@@ -0,0 +1,169 @@
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::transfer::{list_index, set_done, set_due, Field, DEFAULT_LIST};
+use crate::{Error, Priority, Task, TaskList};
+
+/*** Taskwarrior ***/
+// Reads `task export` output and writes what `task import` takes. Task ids are Taskwarrior UUIDs
+// in both directions, so repeated round trips update tasks instead of adding them again
+pub const FIELDS: &[Field] = &[Field::Due, Field::Done, Field::Notes, Field::Priority, Field::Tags, Field::BlockedBy];
+
+const DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/*
+* A task as Taskwarrior exports it, fields the board has no use for are dropped
+* status: String - pending, waiting, completed, deleted or recurring (the template of a recurring task)
+* entry, modified, end, due: Option<String> - UTC times such as 20240503T120000Z
+* annotations: Vec<Annotation> - Timestamped notes, joined into the task's notes
+* depends: Option<Depends> - Blocking tasks, a list or a comma separated string in older versions
+* taskboard_priority: Option<String> - Kept by Taskwarrior as an unknown attribute, remembers urgent
+*     tasks that Taskwarrior only knows as high priority
+*/
+#[derive(Serialize, Deserialize)]
+struct TwTask {
+    uuid: Uuid,
+    description: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    depends: Option<Depends>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    taskboard_priority: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Annotation {
+    #[serde(default)]
+    entry: Option<String>,
+    description: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Depends {
+    List(Vec<Uuid>),
+    Joined(String),
+}
+
+fn format_time(time: DateTime<Local>) -> String {
+    time.with_timezone(&Utc).format(DATE_FORMAT).to_string()
+}
+
+fn parse_time(time: &str) -> Option<DateTime<Local>> {
+    NaiveDateTime::parse_from_str(time, DATE_FORMAT).ok().map(|naive| Utc.from_utc_datetime(&naive).with_timezone(&Local))
+}
+
+/*** Export ***/
+fn tw_task(task: &Task, list: &str) -> TwTask {
+    // a due date without a time is due at the start of that day, as with `task add due:2024-05-03`
+    let due = match task.due_at {
+        _ if task.date_string.is_empty() => None,
+        Some(due_at) => Some(due_at),
+        None => Local.from_local_datetime(&task.due.and_time(NaiveTime::MIN)).earliest(),
+    };
+    TwTask {
+        uuid: task.id,
+        description: task.title.clone(),
+        status: if task.done { "completed" } else { "pending" }.to_string(),
+        entry: Some(format_time(task.created_at)),
+        modified: Some(format_time(task.updated_at)),
+        end: task.completed_at.filter(|_| task.done).map(format_time),
+        due: due.map(format_time),
+        project: Some(list.to_string()),
+        tags: task.tags.iter().cloned().collect(),
+        priority: match task.priority {
+            Priority::None => None,
+            Priority::Low => Some("L".to_string()),
+            Priority::Medium => Some("M".to_string()),
+            Priority::High | Priority::Urgent => Some("H".to_string()),
+        },
+        annotations: match task.notes.is_empty() {
+            true => vec![],
+            false => vec![Annotation { entry: Some(format_time(task.updated_at)), description: task.notes.clone() }],
+        },
+        depends: match task.blocked_by.is_empty() {
+            true => None,
+            false => Some(Depends::List(task.blocked_by.clone())),
+        },
+        taskboard_priority: (task.priority == Priority::Urgent).then(|| "urgent".to_string()),
+    }
+}
+
+pub fn export(lists: &[TaskList]) -> Result<String, Error> {
+    let tasks: Vec<TwTask> = lists.iter().flat_map(|list| list.tasks.iter().map(|task| tw_task(task, &list.title))).collect();
+    Ok(serde_json::to_string_pretty(&tasks)? + "\n")
+}
+
+/*** Import ***/
+// Deleted tasks and the templates of recurring ones have no place on the board and are skipped
+fn board_task(tw: TwTask) -> Option<Task> {
+    if !matches!(tw.status.as_str(), "" | "pending" | "waiting" | "completed") {
+        return None;
+    }
+    let mut task = Task::new(tw.description.trim().to_string());
+    task.id = tw.uuid;
+    if let Some(entry) = tw.entry.as_deref().and_then(parse_time) {
+        task.created_at = entry;
+    }
+    if let Some(due_at) = tw.due.as_deref().and_then(parse_time) {
+        // midnight means a date without a time
+        set_due(&mut task, due_at.date_naive(), Some(due_at).filter(|due_at| due_at.time() != NaiveTime::MIN));
+    }
+    set_done(&mut task, tw.status == "completed", tw.end.as_deref().and_then(parse_time));
+    task.priority = match (tw.taskboard_priority.as_deref(), tw.priority.as_deref()) {
+        (Some("urgent"), Some("H")) => Priority::Urgent,
+        (_, Some("H")) => Priority::High,
+        (_, Some("M")) => Priority::Medium,
+        (_, Some("L")) => Priority::Low,
+        _ => Priority::None,
+    };
+    task.tags = tw.tags.iter().map(|tag| tag.to_lowercase()).collect();
+    task.notes = tw.annotations.iter().map(|annotation| annotation.description.as_str()).collect::<Vec<_>>().join("\n");
+    task.blocked_by = match tw.depends {
+        None => vec![],
+        Some(Depends::List(ids)) => ids,
+        Some(Depends::Joined(ids)) => ids.split(',').filter_map(|id| Uuid::parse_str(id.trim()).ok()).collect(),
+    };
+    Some(task).filter(|task| !task.title.is_empty())
+}
+
+// A JSON array, or one task per line as older versions export
+pub fn import(content: &str) -> Result<Vec<TaskList>, Error> {
+    let tw_tasks: Vec<TwTask> = match content.trim_start().starts_with('[') {
+        true => serde_json::from_str(content).map_err(|err| Error::ImportError(err.to_string()))?,
+        false => content.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| serde_json::from_str(line.trim().trim_end_matches(',')).map_err(|err| Error::ImportError(format!("line {}: {}", number + 1, err))))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let mut lists: Vec<TaskList> = vec![];
+    for tw in tw_tasks {
+        let title = tw.project.clone().filter(|project| !project.is_empty()).unwrap_or(DEFAULT_LIST.to_string());
+        let Some(task) = board_task(tw) else {
+            continue;
+        };
+        let index = list_index(&mut lists, &title);
+        lists[index].tasks.push(task);
+    }
+    Ok(lists)
+}
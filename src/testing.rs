@@ -0,0 +1,51 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+use crate::transfer::new_list;
+use crate::{Task, TaskList};
+
+/*** Helpers shared by the unit tests ***/
+/*
+* A fresh directory under the system temp dir for one test, removed again when dropped so a
+* failing assert leaves nothing behind
+* path: PathBuf - The directory
+*/
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+pub fn temp_dir() -> TempDir {
+    let path = std::env::temp_dir().join(format!("taskboardcli-test-{}", Uuid::new_v4()));
+    fs::create_dir_all(&path).unwrap();
+    TempDir { path }
+}
+
+// A list with one task for each title
+pub fn list(title: &str, tasks: &[&str]) -> TaskList {
+    let mut list = new_list(title);
+    list.tasks = tasks.iter().map(|title| Task::new(title.to_string())).collect();
+    list
+}
+
+// List titles with their task titles, to compare boards without ids and timestamps
+pub fn titles(lists: &[TaskList]) -> Vec<(String, Vec<String>)> {
+    lists.iter().map(|list| (list.title.clone(), list.tasks.iter().map(|task| task.title.clone()).collect())).collect()
+}
@@ -0,0 +1,422 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::{sibling_path, write_atomic};
+use crate::transfer::{list_index, same_title, set_done, set_due, update_task, Field, DEFAULT_LIST};
+use crate::{Error, Priority, Task, TaskBoard, TaskList};
+
+/*** todo.txt lines ***/
+// `x 2024-05-02 2024-04-30 Title +List @tag due:2024-05-03`, with `(A)` to `(D)` in front of open
+// tasks for urgent to low priority and `pri:` keeping it on finished ones. The first +project
+// names the list, further projects and @contexts become tags
+pub const FIELDS: &[Field] = &[Field::Due, Field::Done, Field::Priority, Field::Tags];
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+fn priority_letter(priority: Priority) -> Option<char> {
+    match priority {
+        Priority::None => None,
+        Priority::Low => Some('D'),
+        Priority::Medium => Some('C'),
+        Priority::High => Some('B'),
+        Priority::Urgent => Some('A'),
+    }
+}
+
+// Anything below (D) counts as low
+fn letter_priority(letter: char) -> Option<Priority> {
+    match letter {
+        'A' => Some(Priority::Urgent),
+        'B' => Some(Priority::High),
+        'C' => Some(Priority::Medium),
+        'D'..='Z' => Some(Priority::Low),
+        _ => None,
+    }
+}
+
+// Projects cannot hold spaces
+fn project(list: &str) -> String {
+    list.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Local> {
+    Local.from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap()).earliest().unwrap_or_else(Local::now)
+}
+
+pub fn task_line(task: &Task, list: &str) -> String {
+    let mut words = vec![];
+    if task.done {
+        words.push("x".to_string());
+        words.push(task.completed_at.unwrap_or(task.updated_at).format(DATE_FORMAT).to_string());
+    } else if let Some(letter) = priority_letter(task.priority) {
+        words.push(format!("({})", letter));
+    }
+    words.push(task.created_at.format(DATE_FORMAT).to_string());
+    words.push(task.title.clone());
+    words.push(format!("+{}", project(list)));
+    words.extend(task.tags.iter().map(|tag| format!("@{}", tag)));
+    if !task.date_string.is_empty() {
+        words.push(format!("due:{}", task.due.format(DATE_FORMAT)));
+    }
+    if let (true, Some(letter)) = (task.done, priority_letter(task.priority)) {
+        words.push(format!("pri:{}", letter));
+    }
+    words.join(" ")
+}
+
+// A task and the projects it names, None for blank lines
+pub fn parse_line(line: &str) -> Result<Option<(Task, Vec<String>)>, String> {
+    let mut words = line.split_whitespace().peekable();
+    let mut task = Task::new(String::new());
+    let date = |word: Option<&&str>| word.and_then(|word| NaiveDate::parse_from_str(word, DATE_FORMAT).ok());
+
+    let done = words.next_if_eq(&"x").is_some();
+    if let Some(priority) = words.peek().and_then(|word| word.strip_prefix('(')?.strip_suffix(')')?.parse::<char>().ok()).and_then(letter_priority) {
+        words.next();
+        task.priority = priority;
+    }
+    let first_date = date(words.peek()).inspect(|_| {
+        words.next();
+    });
+    let second_date = first_date.and(date(words.peek())).inspect(|_| {
+        words.next();
+    });
+    // a finished task's first date is when it was finished
+    let (completed, created) = match done {
+        true => (first_date, second_date),
+        false => (None, first_date),
+    };
+    if let Some(created) = created {
+        task.created_at = start_of_day(created);
+    }
+    set_done(&mut task, done, completed.map(start_of_day));
+
+    let mut projects = vec![];
+    let mut title = vec![];
+    for word in words {
+        if let Some(name) = word.strip_prefix('+').filter(|name| !name.is_empty()) {
+            projects.push(name.to_string());
+        } else if let Some(context) = word.strip_prefix('@').filter(|context| !context.is_empty()) {
+            task.tags.insert(context.to_lowercase());
+        } else if let Some(due) = word.strip_prefix("due:") {
+            let due = NaiveDate::parse_from_str(due, DATE_FORMAT).map_err(|_| format!("cannot read the due date \"{}\"", due))?;
+            set_due(&mut task, due, None);
+        } else if let Some(priority) = word.strip_prefix("pri:").and_then(|letter| letter.parse::<char>().ok()).and_then(letter_priority) {
+            task.priority = priority;
+        } else {
+            title.push(word);
+        }
+    }
+    task.tags.extend(projects.iter().skip(1).map(|project| project.to_lowercase()));
+    task.title = title.join(" ");
+    match task.title.is_empty() {
+        true => Ok(None),
+        false => Ok(Some((task, projects))),
+    }
+}
+
+/*** Import and export ***/
+pub fn export(lists: &[TaskList]) -> String {
+    lists.iter()
+        .flat_map(|list| list.tasks.iter().map(|task| format!("{}\n", task_line(task, &list.title))))
+        .collect()
+}
+
+pub fn import(content: &str) -> Result<Vec<TaskList>, Error> {
+    let mut lists: Vec<TaskList> = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let parsed = parse_line(line).map_err(|err| Error::ImportError(format!("line {}: {}", number + 1, err)))?;
+        let Some((task, projects)) = parsed else {
+            continue;
+        };
+        let title = projects.first().map_or(DEFAULT_LIST, String::as_str);
+        let index = list_index(&mut lists, title);
+        lists[index].tasks.push(task);
+    }
+    Ok(lists)
+}
+
+/*** Keeping a list and a todo.txt file in step ***/
+/*
+* A list synced with a todo.txt file
+* list: Uuid - The synced list
+* path: PathBuf - The todo.txt file
+* synced: Vec<(Uuid, String)> - Each task's line as of the last sync, to tell which side changed what
+*/
+#[derive(Serialize, Deserialize)]
+pub struct Link {
+    list: Uuid,
+    path: PathBuf,
+    #[serde(default)]
+    synced: Vec<(Uuid, String)>,
+}
+
+// Links are kept next to the board file as <file>.todotxt
+fn links_path(board_path: &Path) -> PathBuf {
+    sibling_path(board_path, ".todotxt")
+}
+
+fn read_links(path: &Path) -> Vec<Link> {
+    fs::read_to_string(path).ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_links(path: &Path, links: &[Link]) -> Result<(), Error> {
+    write_atomic(path, &serde_json::to_vec_pretty(links)?)?;
+    Ok(())
+}
+
+// Links the list to the file, or unlinks it when `file` is None, and syncs it right away
+pub fn link_list(board_path: &Path, taskboard: &mut TaskBoard, list: &str, file: Option<&Path>) -> Result<String, Error> {
+    let Some(list) = taskboard.lists.iter().find(|candidate| same_title(&candidate.title, list)) else {
+        return Err(Error::NoSuchList(list.to_string()));
+    };
+    let (id, title) = (list.id, list.title.clone());
+    let path = links_path(board_path);
+    let mut links = read_links(&path);
+    links.retain(|link| link.list != id);
+    let Some(file) = file else {
+        write_links(&path, &links)?;
+        return Ok(format!("List {} is no longer synced", title));
+    };
+    // relative paths would change meaning with the directory the TUI is started from
+    let file = std::path::absolute(file)?;
+    links.push(Link { list: id, path: file.clone(), synced: vec![] });
+    write_links(&path, &links)?;
+    sync_links(board_path, taskboard)?;
+    Ok(format!("List {} is synced with {}", title, file.display()))
+}
+
+// Brings every linked list and its file up to date with each other. When a task changed on both
+// sides since the last sync the file wins. Returns a status note when the board changed
+pub fn sync_links(board_path: &Path, taskboard: &mut TaskBoard) -> Result<Option<String>, Error> {
+    let path = links_path(board_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut links = read_links(&path);
+    let count = links.len();
+    links.retain(|link| taskboard.lists.iter().any(|list| list.id == link.list));
+    let mut links_changed = links.len() != count;
+
+    let before = taskboard.lists.clone();
+    let mut pulled = 0;
+    for link in links.iter_mut() {
+        let list = taskboard.lists.iter_mut().find(|list| list.id == link.list).unwrap();
+        let (changes, synced) = sync_list(link, list)?;
+        pulled += changes;
+        if synced != link.synced {
+            link.synced = synced;
+            links_changed = true;
+        }
+    }
+    if links_changed {
+        write_links(&path, &links)?;
+    }
+    if pulled == 0 {
+        return Ok(None);
+    }
+    crate::stamp_changes(&before, &mut taskboard.lists);
+    for list in taskboard.lists.iter_mut() {
+        crate::fix_selection(list, &taskboard.tag_filter);
+    }
+    Ok(Some(match pulled {
+        1 => "Took 1 change from todo.txt".to_string(),
+        n => format!("Took {} changes from todo.txt", n),
+    }))
+}
+
+// Applies what changed in the file to the list, then writes the list back to the file. Returns the
+// number of tasks changed and the new synced lines. A missing or emptied file is written again from
+// the list rather than taken as every task removed there
+fn sync_list(link: &Link, list: &mut TaskList) -> Result<(usize, Vec<(Uuid, String)>), Error> {
+    let content = match fs::read_to_string(&link.path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let lines: Vec<&str> = content.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    let parse = |line: &str| parse_line(line).map_err(|err| Error::ImportError(format!("{}: {}", link.path.display(), err)));
+
+    let mut changes = 0;
+    if !lines.is_empty() {
+        // synced lines missing from the file belong to tasks that were edited or removed there
+        let mut gone: Vec<(Uuid, Option<Task>)> = link.synced.iter()
+            .filter(|(_, line)| !lines.contains(&line.as_str()))
+            .map(|(id, line)| (*id, parse_line(line).ok().flatten().map(|(task, _)| task)))
+            .collect();
+        let mut added: Vec<(Option<Uuid>, Task, bool)> = vec![];
+        for line in lines.iter().filter(|line| !link.synced.iter().any(|(_, synced)| synced == *line)) {
+            let Some((mut task, projects)) = parse(line)? else {
+                continue;
+            };
+            task.tags.extend(projects.iter().filter(|name| **name != project(&list.title)).map(|name| name.to_lowercase()));
+            // lines typed without a creation date are dated today and say nothing about which task they were
+            let created = task.created_at.format(DATE_FORMAT).to_string();
+            let dated = line.split_whitespace().take(3).any(|word| word == created);
+            added.push((None, task, dated));
+        }
+
+        // an edited line is paired with the line it replaced by title, then by creation date in file
+        // order, and a single new line with a single gone one whatever they hold
+        let same_title = |old: &Task, new: &Task, _| old.title == new.title;
+        let same_day = |old: &Task, new: &Task, dated| dated && old.created_at.date_naive() == new.created_at.date_naive();
+        for pairs in [&same_title as &dyn Fn(&Task, &Task, bool) -> bool, &same_day] {
+            for (edited, task, dated) in added.iter_mut().filter(|(edited, _, _)| edited.is_none()) {
+                if let Some(i) = gone.iter().position(|(_, old)| old.as_ref().is_some_and(|old| pairs(old, task, *dated))) {
+                    *edited = Some(gone.remove(i).0);
+                }
+            }
+        }
+        let unpaired: Vec<usize> = (0..added.len()).filter(|i| added[*i].0.is_none()).collect();
+        if let ([i], [_]) = (unpaired.as_slice(), gone.as_slice()) {
+            added[*i].0 = gone.pop().map(|(id, _)| id);
+        }
+
+        for (edited, task, _) in added {
+            // a task on the board that was never synced is matched by title
+            let existing = match edited {
+                Some(id) => list.tasks.iter().position(|old| old.id == id),
+                None => list.tasks.iter().position(|old| old.title == task.title && !link.synced.iter().any(|(id, _)| *id == old.id)),
+            };
+            match existing {
+                Some(i) => {
+                    let old = list.tasks[i].clone();
+                    update_task(&mut list.tasks[i], &task, FIELDS);
+                    changes += (list.tasks[i] != old) as usize;
+                }
+                None => {
+                    list.tasks.push(task);
+                    changes += 1;
+                }
+            }
+        }
+        for (id, _) in gone {
+            if let Some(i) = list.tasks.iter().position(|task| task.id == id) {
+                list.tasks.remove(i);
+                changes += 1;
+            }
+        }
+    }
+
+    crate::sort_tasks(list);
+    let synced: Vec<(Uuid, String)> = list.tasks.iter().map(|task| (task.id, task_line(task, &list.title))).collect();
+    if synced.iter().map(|(_, line)| line.as_str()).ne(lines.iter().copied()) {
+        let content: String = synced.iter().map(|(_, line)| format!("{}\n", line)).collect();
+        write_atomic(&link.path, content.as_bytes())?;
+    }
+    Ok((changes, synced))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{list, temp_dir, TempDir};
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, DATE_FORMAT).unwrap()
+    }
+
+    // A list synced with a todo.txt file in a fresh directory, and the link holding its synced lines
+    fn synced_list(titles: &[&str]) -> (TempDir, TaskList, Link) {
+        let dir = temp_dir();
+        let mut list = list("Work", titles);
+        let mut link = Link { list: list.id, path: dir.join("todo.txt"), synced: vec![] };
+        let (_, synced) = sync_list(&link, &mut list).unwrap();
+        link.synced = synced;
+        (dir, list, link)
+    }
+
+    fn file_lines(link: &Link) -> Vec<String> {
+        fs::read_to_string(&link.path).unwrap().lines().map(str::to_string).collect()
+    }
+
+    fn write_lines(link: &Link, lines: &[String]) {
+        fs::write(&link.path, lines.iter().map(|line| format!("{}\n", line)).collect::<String>()).unwrap();
+    }
+
+    #[test]
+    fn parse_line_reads_every_part() {
+        let (task, projects) = parse_line("(B) 2024-04-30 Call the bank +Errands +Home @phone due:2024-05-03").unwrap().unwrap();
+        assert_eq!(task.title, "Call the bank");
+        assert_eq!(task.priority, Priority::High);
+        assert_eq!(task.created_at.date_naive(), date("2024-04-30"));
+        assert_eq!(task.due, date("2024-05-03"));
+        assert!(!task.date_string.is_empty());
+        assert_eq!(projects, ["Errands", "Home"]);
+        assert!(task.tags.contains("phone") && task.tags.contains("home"));
+
+        let (task, _) = parse_line("x 2024-05-02 2024-04-30 Pay rent pri:A").unwrap().unwrap();
+        assert!(task.done);
+        assert_eq!(task.completed_at.map(|at| at.date_naive()), Some(date("2024-05-02")));
+        assert_eq!(task.created_at.date_naive(), date("2024-04-30"));
+        assert_eq!(task.priority, Priority::Urgent);
+        assert_eq!(task_line(&task, "Bills"), "x 2024-05-02 2024-04-30 Pay rent +Bills pri:A");
+
+        assert!(parse_line("   ").unwrap().is_none());
+        assert!(parse_line("Taxes due:soon").is_err());
+    }
+
+    #[test]
+    fn a_missing_or_emptied_file_is_written_again() {
+        let (_dir, mut list, link) = synced_list(&["Report", "Review"]);
+        fs::remove_file(&link.path).unwrap();
+        let (changes, _) = sync_list(&link, &mut list).unwrap();
+        assert_eq!(changes, 0);
+        assert_eq!(list.tasks.len(), 2);
+        assert_eq!(file_lines(&link).len(), 2);
+
+        fs::write(&link.path, "\n").unwrap();
+        let (changes, _) = sync_list(&link, &mut list).unwrap();
+        assert_eq!(changes, 0);
+        assert_eq!(file_lines(&link).len(), 2);
+    }
+
+    #[test]
+    fn an_edited_title_keeps_the_task() {
+        let (_dir, mut list, mut link) = synced_list(&["Report", "Review"]);
+        list.tasks[0].notes = "for the board".to_string();
+        let id = list.tasks[0].id;
+        let lines: Vec<String> = file_lines(&link).into_iter().map(|line| line.replace("Report", "Quarterly report")).collect();
+        write_lines(&link, &lines);
+
+        let (changes, synced) = sync_list(&link, &mut list).unwrap();
+        assert_eq!(changes, 1);
+        assert_eq!(list.tasks.len(), 2);
+        let task = list.tasks.iter().find(|task| task.id == id).unwrap();
+        assert_eq!(task.title, "Quarterly report");
+        assert_eq!(task.notes, "for the board");
+        link.synced = synced;
+
+        // two edits on the same day pair up in file order
+        let lines: Vec<String> = file_lines(&link).into_iter().map(|line| line.replace("report", "summary").replace("Review", "Proofread")).collect();
+        write_lines(&link, &lines);
+        let ids: Vec<Uuid> = list.tasks.iter().map(|task| task.id).collect();
+        sync_list(&link, &mut list).unwrap();
+        let mut titles: Vec<&str> = list.tasks.iter().map(|task| task.title.as_str()).collect();
+        titles.sort();
+        assert_eq!(titles, ["Proofread", "Quarterly summary"]);
+        assert!(list.tasks.iter().all(|task| ids.contains(&task.id)));
+    }
+
+    #[test]
+    fn lines_removed_and_added_in_the_file_reach_the_list() {
+        let (_dir, mut list, link) = synced_list(&["Report", "Review"]);
+        let mut lines: Vec<String> = file_lines(&link).into_iter().filter(|line| !line.contains("Review")).collect();
+        lines.push("(A) Ship it +Work".to_string());
+        lines.push("Water plants".to_string());
+        write_lines(&link, &lines);
+
+        let (changes, _) = sync_list(&link, &mut list).unwrap();
+        assert_eq!(changes, 3);
+        let titles: Vec<&str> = list.tasks.iter().map(|task| task.title.as_str()).collect();
+        assert!(titles.contains(&"Ship it") && titles.contains(&"Water plants") && !titles.contains(&"Review"));
+        assert!(file_lines(&link).iter().all(|line| line.contains("+Work")));
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use uuid::Uuid;
+
+use crate::db::{board_path, write_atomic};
+use crate::{ical, markdown, spreadsheet, taskwarrior, todotxt, Error, Task, TaskBoard, TaskList};
+
+/*** Import and export formats ***/
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Markdown,
+    TodoTxt,
+    // VTODO entries, or all-day VEVENT entries with `ics-events`
+    ICalendar,
+    ICalendarEvents,
+    Csv,
+    Taskwarrior,
+}
+
+impl Format {
+    fn export(self, lists: &[TaskList]) -> Result<String, Error> {
+        match self {
+            Format::Markdown => Ok(markdown::export(lists)),
+            Format::TodoTxt => Ok(todotxt::export(lists)),
+            Format::ICalendar => Ok(ical::export(lists, false)),
+            Format::ICalendarEvents => Ok(ical::export(lists, true)),
+            Format::Csv => spreadsheet::export(lists),
+            Format::Taskwarrior => taskwarrior::export(lists),
+        }
+    }
+
+    fn import(self, content: &str, options: &ImportOptions) -> Result<Imported, Error> {
+        match self {
+            Format::Markdown => Ok(Imported::new(markdown::import(content)?, markdown::FIELDS)),
+            Format::TodoTxt => Ok(Imported::new(todotxt::import(content)?, todotxt::FIELDS)),
+            Format::ICalendar | Format::ICalendarEvents => Ok(Imported::new(ical::import(content)?, ical::FIELDS)),
+            Format::Csv => spreadsheet::import(content, options),
+            Format::Taskwarrior => Ok(Imported::new(taskwarrior::import(content)?, taskwarrior::FIELDS)),
+        }
+    }
+
+    // Formats without ids of their own match imported tasks to existing ones by title instead
+    fn has_ids(self) -> bool {
+        match self {
+            Format::Markdown | Format::TodoTxt | Format::Csv => false,
+            Format::ICalendar | Format::ICalendarEvents | Format::Taskwarrior => true,
+        }
+    }
+}
+
+// Task fields besides the title that an imported file can carry. Each format names the ones it
+// carries in its FIELDS, CSV goes by the columns it finds
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Field {
+    Due,
+    Done,
+    Notes,
+    Priority,
+    Tags,
+    Parent,
+    BlockedBy,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(Format::Markdown),
+            "todotxt" | "todo.txt" => Ok(Format::TodoTxt),
+            "ics" | "ical" | "icalendar" => Ok(Format::ICalendar),
+            "ics-events" => Ok(Format::ICalendarEvents),
+            "csv" => Ok(Format::Csv),
+            "taskwarrior" | "tw" => Ok(Format::Taskwarrior),
+            _ => Err(format!("unknown format: {} (expected markdown, todotxt, ics, ics-events, csv or taskwarrior)", input)),
+        }
+    }
+}
+
+/*
+* Settings for reading files whose layout varies, only CSV so far
+* columns: Vec<(String, String)> - Task field and the header of the column that holds it
+* date_format: Option<String> - chrono format of due dates, instead of YYYY-MM-DD with an optional HH:MM
+*/
+#[derive(Default)]
+pub struct ImportOptions {
+    pub columns: Vec<(String, String)>,
+    pub date_format: Option<String>,
+}
+
+/*
+* What was read from a file
+* lists: Vec<TaskList> - The tasks, grouped into lists
+* fields: Vec<Field> - What the file says about each task besides its title, the rest is left alone
+* rejected: Vec<String> - Rows that could not be read, shown before the rest is imported
+*/
+pub struct Imported {
+    pub lists: Vec<TaskList>,
+    pub fields: Vec<Field>,
+    pub rejected: Vec<String>,
+}
+
+impl Imported {
+    fn new(lists: Vec<TaskList>, fields: &[Field]) -> Imported {
+        Imported { lists, fields: fields.to_vec(), rejected: vec![] }
+    }
+}
+
+/*** Helpers shared by the formats ***/
+// Where tasks go that a file does not assign to a list
+pub const DEFAULT_LIST: &str = "Imported";
+
+pub fn new_list(title: &str) -> TaskList {
+    let now = Local::now();
+    TaskList {
+        id: Uuid::new_v4(),
+        title: title.to_string(),
+        tasks: vec![],
+        selected: 0,
+        hide_completed: false,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+// Gives a task a due date, with a deadline when a time of day is known
+pub fn set_due(task: &mut Task, due: NaiveDate, due_at: Option<DateTime<Local>>) {
+    task.due = due;
+    task.due_at = due_at;
+    task.date_string = crate::short_date(due);
+}
+
+pub fn set_done(task: &mut Task, done: bool, completed_at: Option<DateTime<Local>>) {
+    task.done = done;
+    task.completed_at = match done {
+        true => completed_at.or(Some(Local::now())),
+        false => None,
+    };
+}
+
+// "YYYY-MM-DD" or "YYYY-MM-DD HH:MM"
+pub fn parse_iso_due(input: &str) -> Option<(NaiveDate, Option<DateTime<Local>>)> {
+    let input = input.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        return Some((date, None));
+    }
+    let naive = NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M").ok()?;
+    let due_at = Local.from_local_datetime(&naive).earliest()?;
+    Some((due_at.date_naive(), Some(due_at)))
+}
+
+pub fn iso_due(task: &Task) -> Option<String> {
+    match (task.date_string.is_empty(), task.due_at) {
+        (true, _) => None,
+        (false, Some(due_at)) => Some(due_at.format("%Y-%m-%d %H:%M").to_string()),
+        (false, None) => Some(task.due.format("%Y-%m-%d").to_string()),
+    }
+}
+
+// List titles match ignoring case and with spaces and underscores alike, so "Work_items" from a
+// todo.txt project finds the list "Work items"
+pub fn same_title(a: &str, b: &str) -> bool {
+    let normalize = |title: &str| title.split(|c: char| c.is_whitespace() || c == '_').filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" ").to_lowercase();
+    normalize(a) == normalize(b)
+}
+
+// The index of the list with the given title, added at the end when there is none yet
+pub fn list_index(lists: &mut Vec<TaskList>, title: &str) -> usize {
+    match lists.iter().position(|list| same_title(&list.title, title)) {
+        Some(index) => index,
+        None => {
+            lists.push(new_list(title));
+            lists.len() - 1
+        }
+    }
+}
+
+// The lists to export: the whole board, or just the list with the given title
+fn pick_lists(lists: Vec<TaskList>, list: Option<&str>) -> Result<Vec<TaskList>, Error> {
+    match list {
+        None => Ok(lists),
+        Some(title) => match lists.into_iter().find(|candidate| same_title(&candidate.title, title)) {
+            Some(list) => Ok(vec![list]),
+            None => Err(Error::NoSuchList(title.to_string())),
+        },
+    }
+}
+
+/*** Commands ***/
+// `export <format> [file]` writes the board, or one list with --list, to the file or stdout
+pub fn export_command(db_path: &Path, board_name: &str, format: Format, list: Option<&str>, file: Option<&Path>) -> Result<(), Error> {
+    let (_, db, _) = crate::load_board(&board_path(db_path, board_name))?;
+    let output = format.export(&pick_lists(db.lists, list)?)?;
+    match file {
+        Some(file) => {
+            write_atomic(file, output.as_bytes())?;
+            println!("Exported to {}", file.display());
+        }
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+// `import <format> <file>` adds the file's tasks to the board, into lists of the same title or the
+// one given with --list. Tasks that are already on the board are updated instead of duplicated,
+// and the whole import is one undo step
+pub fn import_command(
+    db_path: &Path,
+    board_name: &str,
+    format: Format,
+    list: Option<&str>,
+    file: &Path,
+    options: &ImportOptions,
+) -> Result<(), Error> {
+    let imported = format.import(&fs::read_to_string(file)?, options)?;
+    if !imported.rejected.is_empty() {
+        for row in imported.rejected.iter() {
+            eprintln!("{}", row);
+        }
+        let count = imported.lists.iter().map(|list| list.tasks.len()).sum::<usize>();
+        eprint!("{} rows could not be read. Import the other {}? [y/N] ", imported.rejected.len(), count);
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            println!("Nothing imported");
+            return Ok(());
+        }
+    }
+    let (added, updated, dropped) = change_board(db_path, board_name, |taskboard| Ok(merge_import(taskboard, imported, list, format)))?;
+    for blocker in dropped {
+        eprintln!("{}", blocker);
+    }
+    println!("Imported {} new and {} updated tasks from {}", added, updated, file.display());
+    Ok(())
+}
+
+// Edits a board from the command line the way the TUI would: as one undo step that is logged,
+// saved through the board's lock and merged with any instance that has it open
+pub fn change_board<T>(db_path: &Path, board_name: &str, change: impl FnOnce(&mut TaskBoard) -> Result<T, Error>) -> Result<T, Error> {
+    let path = board_path(db_path, board_name);
+    let (mut board, db, _) = crate::load_board(&path)?;
+    let mut taskboard = crate::new_taskboard(db, board_name.to_string(), String::new(), board.history());
+
+    let before = taskboard.lists.clone();
+    let result = change(&mut taskboard)?;
+    crate::stamp_changes(&before, &mut taskboard.lists);
+    taskboard.num_lists = taskboard.lists.len();
+    crate::update_dates(&mut taskboard);
+    let events = taskboard.history.record(&taskboard.lists);
+    taskboard.activity.extend(events);
+    board.save(&mut taskboard)?;
+    Ok(result)
+}
+
+// Returns how many tasks were added and how many existing ones changed, and the blockers that were
+// left out because they would block a task by itself or close a cycle
+fn merge_import(taskboard: &mut TaskBoard, imported: Imported, into: Option<&str>, format: Format) -> (usize, usize, Vec<String>) {
+    let lists = &mut taskboard.lists;
+    let mut added = 0;
+    // existing tasks as they were before the import, to count those that changed in the end
+    let mut originals: Vec<Task> = vec![];
+    let mut linked = vec![];
+    for source in imported.lists {
+        let title = into.unwrap_or(&source.title);
+        let target = list_index(lists, title);
+
+        // imported id -> id on the board, to point subtasks at the right parent
+        let mut ids: HashMap<Uuid, Uuid> = HashMap::new();
+        let mut touched = vec![];
+        for mut task in source.tasks {
+            task.parent = task.parent.map(|parent| ids.get(&parent).copied().unwrap_or(parent));
+            let existing = lists.iter().enumerate()
+                .find_map(|(l, list)| list.tasks.iter().position(|old| old.id == task.id).map(|t| (l, t)))
+                .or_else(|| match format.has_ids() {
+                    true => None,
+                    false => lists[target].tasks.iter()
+                        .position(|old| old.title == task.title && !touched.contains(&old.id))
+                        .map(|t| (target, t)),
+                });
+            match existing {
+                Some((l, t)) => {
+                    let old = &mut lists[l].tasks[t];
+                    originals.push(old.clone());
+                    update_task(old, &task, &imported.fields);
+                    ids.insert(task.id, old.id);
+                    touched.push(old.id);
+                }
+                None => {
+                    ids.insert(task.id, task.id);
+                    touched.push(task.id);
+                    lists[target].tasks.push(task);
+                    added += 1;
+                }
+            }
+        }
+        for list in lists.iter_mut() {
+            for task in list.tasks.iter_mut().filter(|task| touched.contains(&task.id)) {
+                task.parent = task.parent.map(|parent| ids.get(&parent).copied().unwrap_or(parent));
+                for blocker in task.blocked_by.iter_mut() {
+                    *blocker = ids.get(blocker).copied().unwrap_or(*blocker);
+                }
+            }
+        }
+        linked.extend(touched);
+    }
+
+    // blockers from the file are linked one by one with the checks the TUI makes
+    let mut dropped = vec![];
+    let pending: Vec<(Uuid, Vec<Uuid>)> = match imported.fields.contains(&Field::BlockedBy) {
+        true => lists.iter_mut()
+            .flat_map(|list| list.tasks.iter_mut())
+            .filter(|task| linked.contains(&task.id))
+            .map(|task| (task.id, std::mem::take(&mut task.blocked_by)))
+            .collect(),
+        false => vec![],
+    };
+    let title = |taskboard: &TaskBoard, id| crate::find_task(taskboard, id).map_or(id.to_string(), |task| task.title.clone());
+    for (id, blockers) in pending {
+        for blocker in blockers {
+            if let Err(err) = crate::check_dependency(taskboard, id, blocker) {
+                dropped.push(format!("{} is not blocked by {}: {}", title(taskboard, id), title(taskboard, blocker), err));
+                continue;
+            }
+            if let Some(task) = taskboard.lists.iter_mut().flat_map(|list| list.tasks.iter_mut()).find(|task| task.id == id) {
+                if !task.blocked_by.contains(&blocker) {
+                    task.blocked_by.push(blocker);
+                }
+            }
+        }
+    }
+    let updated = originals.iter().filter(|old| crate::find_task(taskboard, old.id) != Some(*old)).count();
+    (added, updated, dropped)
+}
+
+// Takes over the title and whichever other fields the file carries, the rest are left alone. A due
+// date without a time keeps a deadline already set on that day
+pub fn update_task(task: &mut Task, imported: &Task, fields: &[Field]) {
+    task.title = imported.title.clone();
+    for field in fields {
+        match field {
+            Field::Due => {
+                let due_at = imported.due_at.or(task.due_at.filter(|due_at| due_at.date_naive() == imported.due && !imported.date_string.is_empty()));
+                // keep the current label unless the due date itself changed
+                if (task.due, task.due_at, task.date_string.is_empty()) != (imported.due, due_at, imported.date_string.is_empty()) {
+                    task.due = imported.due;
+                    task.due_at = due_at;
+                    task.date_string = imported.date_string.clone();
+                }
+            }
+            Field::Done => {
+                if task.done != imported.done {
+                    set_done(task, imported.done, imported.completed_at);
+                }
+            }
+            Field::Notes => task.notes = imported.notes.clone(),
+            Field::Priority => task.priority = imported.priority,
+            Field::Tags => task.tags = imported.tags.clone(),
+            Field::Parent => task.parent = imported.parent,
+            Field::BlockedBy => task.blocked_by = imported.blocked_by.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::History;
+    use crate::{DbFile, Priority, MAIN_BOARD};
+
+    fn board(lists: Vec<TaskList>) -> TaskBoard {
+        let db = DbFile { lists, ..DbFile::default() };
+        crate::new_taskboard(db, MAIN_BOARD.to_string(), String::new(), History::default())
+    }
+
+    fn import_into(taskboard: &mut TaskBoard, format: Format, content: &str) -> (usize, usize, Vec<String>) {
+        let imported = format.import(content, &ImportOptions::default()).unwrap();
+        merge_import(taskboard, imported, None, format)
+    }
+
+    fn task_count(taskboard: &TaskBoard) -> usize {
+        taskboard.lists.iter().map(|list| list.tasks.len()).sum()
+    }
+
+    #[test]
+    fn taskwarrior_round_trip_adds_no_duplicates() {
+        let mut work = new_list("Work");
+        let mut home = new_list("Home");
+        let mut report = Task::new("Report".to_string());
+        report.priority = Priority::Urgent;
+        report.tags = crate::parse_tags("q2");
+        report.notes = "for the board".to_string();
+        set_due(&mut report, NaiveDate::from_ymd_opt(2024, 5, 3).unwrap(), None);
+        let mut review = Task::new("Review".to_string());
+        review.blocked_by = vec![report.id];
+        work.tasks = vec![report.clone(), review.clone()];
+        home.tasks.push(Task::new("Laundry".to_string()));
+        let mut taskboard = board(vec![work, home]);
+
+        let exported = Format::Taskwarrior.export(&taskboard.lists).unwrap();
+        let (added, updated, dropped) = import_into(&mut taskboard, Format::Taskwarrior, &exported);
+        assert_eq!((added, updated), (0, 0));
+        assert!(dropped.is_empty());
+        assert_eq!(task_count(&taskboard), 3);
+
+        // and into an empty board everything comes back as it was
+        let mut fresh = board(vec![]);
+        let (added, _, _) = import_into(&mut fresh, Format::Taskwarrior, &exported);
+        assert_eq!(added, 3);
+        let titles: Vec<&str> = fresh.lists.iter().map(|list| list.title.as_str()).collect();
+        assert_eq!(titles, ["Work", "Home"]);
+        let read = crate::find_task(&fresh, report.id).unwrap();
+        assert_eq!((read.priority, &read.tags, &read.notes, read.due), (report.priority, &report.tags, &report.notes, report.due));
+        assert_eq!(crate::find_task(&fresh, review.id).unwrap().blocked_by, [report.id]);
+    }
+
+    #[test]
+    fn imported_blockers_cannot_block_themselves_or_close_a_cycle() {
+        let mut work = new_list("Work");
+        let ship = Task::new("Ship".to_string());
+        let mut test = Task::new("Test".to_string());
+        test.blocked_by = vec![ship.id];
+        work.tasks = vec![ship.clone(), test.clone()];
+        let mut taskboard = board(vec![work]);
+
+        let content = format!(
+            "[{{\"uuid\":\"{}\",\"description\":\"Ship\",\"status\":\"pending\",\"project\":\"Work\",\"depends\":[\"{}\",\"{}\"]}}]",
+            ship.id, ship.id, test.id,
+        );
+        let (_, updated, dropped) = import_into(&mut taskboard, Format::Taskwarrior, &content);
+        assert_eq!(updated, 0);
+        assert_eq!(dropped, [
+            "Ship is not blocked by Ship: a task cannot block itself",
+            "Ship is not blocked by Test: dependency cycle: Ship -> Test -> Ship",
+        ]);
+        assert!(crate::find_task(&taskboard, ship.id).unwrap().blocked_by.is_empty());
+        assert_eq!(crate::find_task(&taskboard, test.id).unwrap().blocked_by, [ship.id]);
+    }
+
+    #[test]
+    fn formats_without_ids_match_by_title() {
+        let mut taskboard = board(vec![]);
+        let content = "# Work items\n\n- [ ] Report (due 2024-05-03)\n- [x] Review\n";
+        assert_eq!(import_into(&mut taskboard, Format::Markdown, content).0, 2);
+        let (added, updated, _) = import_into(&mut taskboard, Format::Markdown, &content.replace("# Work items", "# work_items").replace("[x]", "[ ]"));
+        assert_eq!((added, updated), (0, 1));
+        assert_eq!(taskboard.lists.len(), 1);
+        assert_eq!(task_count(&taskboard), 2);
+    }
+}